@@ -1,43 +1,565 @@
-use std::io::{self, BufRead};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::collections::HashMap;
 
 fn main() {
-    let mut lines = Vec::new();
-    let stdin = io::stdin();
+    let mut brute = false;
+    let mut strict = false;
+    let mut lenient = false;
+    let mut batch = false;
+    let mut stats = false;
+    let mut csv = None;
+    let mut json = None;
+    let mut files = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--brute" => brute = true,
+            "--strict" => strict = true,
+            "--lenient" => lenient = true,
+            "--batch" => batch = true,
+            "--stats" => stats = true,
+            _ if arg.starts_with("--csv=") => csv = Some(arg["--csv=".len()..].to_string()),
+            _ if arg.starts_with("--json=") => json = Some(arg["--json=".len()..].to_string()),
+            _ => files.push(arg),
+        }
+    }
+    if batch && (stats || csv.is_some() || json.is_some()) {
+        eprintln!("--stats, --csv and --json only work with a single device");
+        std::process::exit(1);
+    }
+
+    let (devices, bad) = read_input(&files, lenient, batch);
+    for token in &bad {
+        token.print();
+    }
+    if strict && !bad.is_empty() {
+        eprintln!("{} bad tokens, giving up", bad.len());
+        std::process::exit(1);
+    }
+
+    if batch {
+        print_table(&devices, brute);
+        return;
+    }
+
+    let lines = &devices[0].lines;
+    let sums = match prefix_sums(lines) {
+        Ok(sums) => sums,
+        Err(overflow) => {
+            overflow.print("p1");
+            std::process::exit(1);
+        }
+    };
+    part1(&sums);
+    let repeat = part2(lines, &sums, brute);
+    export(lines, &sums, &repeat, stats, &csv, &json);
+}
+
+// A list of changes for a single device.
+struct Device {
+    label: String,
+    lines: Vec<i64>,
+}
+impl Device {
+    fn new(count: usize, label: &str) -> Device {
+        let label = if label.is_empty() { format!("device {}", count + 1) } else { label.to_string() };
+        Device { label, lines: Vec::new() }
+    }
+}
+
+// A token in the input that could not be parsed, or a line that could not
+// be read at all; line and column are one-based.
+struct BadToken {
+    file: String,
+    line: usize,
+    col: usize,
+    text: String,
+    error: Option<String>,
+}
+impl BadToken {
+    fn print(&self) {
+        if let Some(ref error) = self.error {
+            eprintln!("{}:{}:{}: {}", self.file, self.line, self.col, error);
+        } else if self.text.is_empty() {
+            eprintln!("{}:{}:{}: empty line", self.file, self.line, self.col);
+        } else {
+            eprintln!("{}:{}:{}: that was not a number: {}", self.file, self.line, self.col, self.text);
+        }
+    }
+}
+
+// Read all numbers from the given files, or from stdin if there are none.
+// By default there must be exactly one number per line; in lenient mode we
+// also accept the puzzle text forms: several numbers on one line separated by
+// commas, blank lines and comments starting with '#'.
+//
+// In batch mode each file holds one or more devices, separated by blank lines
+// or by header lines such as "# device A", which also give the device a name.
+fn read_input(files: &[String], lenient: bool, batch: bool) -> (Vec<Device>, Vec<BadToken>) {
+    let mut devices = vec![Device::new(0, "")];
+    let mut bad = Vec::new();
+    if files.is_empty() {
+        let stdin = io::stdin();
+        read_source("<stdin>", stdin.lock(), lenient, batch, &mut devices, &mut bad);
+    }
+    for name in files {
+        let file = match File::open(name) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                std::process::exit(1);
+            }
+        };
+        if batch {
+            start_device(&mut devices, "");
+        }
+        read_source(name, BufReader::new(file), lenient, batch, &mut devices, &mut bad);
+    }
+    if batch && devices.len() > 1 && devices[devices.len() - 1].lines.is_empty() {
+        devices.pop();
+    }
+    (devices, bad)
+}
+
+// Start a new device, unless the current one is still empty and unnamed.
+fn start_device(devices: &mut Vec<Device>, label: &str) {
+    let count = devices.len();
+    if devices[count - 1].lines.is_empty() {
+        if !label.is_empty() {
+            devices[count - 1] = Device::new(count - 1, label);
+        }
+        return;
+    }
+    devices.push(Device::new(count, label));
+}
+
+fn read_source<R: BufRead>(name: &str, reader: R, lenient: bool, batch: bool,
+                           devices: &mut Vec<Device>, bad: &mut Vec<BadToken>) {
+    for (pos, line) in reader.split(b'\n').enumerate() {
+        let unreadable = |error: String| BadToken { file: name.to_string(), line: pos + 1, col: 1,
+                                                    text: String::new(), error: Some(error) };
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                bad.push(unreadable(e.to_string()));
+                break;
+            }
+        };
+        let mut line = match String::from_utf8(line) {
+            Ok(line) => line,
+            Err(_) => {
+                bad.push(unreadable("not valid UTF-8".to_string()));
+                continue;
+            }
+        };
+        if line.ends_with('\r') {
+            line.pop();
+        }
+        if batch {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                start_device(devices, "");
+                continue;
+            }
+            if let Some(header) = trimmed.strip_prefix('#') {
+                start_device(devices, header.trim());
+                continue;
+            }
+        }
+        let lines = &mut devices.last_mut().unwrap().lines;
+        let tokens = if lenient { split_lenient(&line) } else { split_strict(&line) };
+        for (col, text) in tokens {
+            match text.parse::<i64>() {
+                Ok(num) => lines.push(num),
+                Err(_) => bad.push(BadToken { file: name.to_string(), line: pos + 1, col, text, error: None }),
+            }
+        }
+    }
+}
+
+// The whole line (minus surrounding blanks) is a single token.
+fn split_strict(line: &str) -> Vec<(usize, String)> {
+    let skipped = line.chars().take_while(|c| c.is_whitespace()).count();
+    vec![(skipped + 1, line.trim().to_string())]
+}
+
+// Tokens are separated by commas and blanks, and a '#' starts a comment.
+fn split_lenient(line: &str) -> Vec<(usize, String)> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut start = 0;
+    for (col, c) in line.chars().enumerate() {
+        if c == '#' {
+            break;
+        }
+        if c == ',' || c.is_whitespace() {
+            if !text.is_empty() {
+                tokens.push((start + 1, text));
+                text = String::new();
+            }
+            continue;
+        }
+        if text.is_empty() {
+            start = col;
+        }
+        text.push(c);
+    }
+    if !text.is_empty() {
+        tokens.push((start + 1, text));
+    }
+    tokens
+}
+
+// Frequency overflowed an i64 when applying the change at this pass / step.
+struct Overflow {
+    pass: usize,
+    step: usize,
+}
+impl Overflow {
+    fn print(&self, name: &str) {
+        println!("{}: overflow at pass {} step {}", name, self.pass, self.step);
+    }
+}
+
+// The frequencies reached during the first pass.
+fn prefix_sums(lines: &[i64]) -> Result<Vec<i64>, Overflow> {
+    let mut sums = Vec::with_capacity(lines.len());
+    let mut total: i64 = 0;
+    for (step, num) in lines.iter().enumerate() {
+        total = match total.checked_add(*num) {
+            Some(total) => total,
+            None => return Err(Overflow { pass: 0, step }),
+        };
+        sums.push(total);
+    }
+    Ok(sums)
+}
+
+fn part1(sums: &[i64]) {
+    let total = sums.last().cloned().unwrap_or(0);
+    println!("p1: {} ({})", total, sums.len());
+}
+
+fn part2(lines: &[i64], sums: &[i64], brute: bool) -> Option<Repeat> {
+    let repeat = first_repeat(sums);
+    match repeat {
+        Some(ref r) => r.print("p2"),
+        None => println!("p2: no frequency is ever repeated"),
+    }
+    if !brute {
+        return repeat;
+    }
+
+    // the brute force loop never ends when there is no repeat, so only
+    // use it to double check an answer we already have
+    if repeat.is_none() {
+        println!("brute: skipped, it would never finish");
+        return repeat;
+    }
+    match first_repeat_brute(lines) {
+        Ok(oracle) => {
+            oracle.print("brute");
+            if repeat.as_ref() != Some(&oracle) {
+                println!("brute: MISMATCH");
+                std::process::exit(1);
+            }
+        }
+        Err(overflow) => overflow.print("brute"),
+    }
+    repeat
+}
 
-    // read all of stdin and convert to a number array
-    // TODO: this can probably made more idiomatic
-    for line in stdin.lock().lines() {
-        let line = line.unwrap();
-        match line.parse::<i32>() {
-            Ok(num) => lines.push(num),
-            Err(_) => println!("That was not a number: {}", line),
+fn print_table(devices: &[Device], brute: bool) {
+    println!("{:<16} {:>8} {:>20} {:>20} {:>12} {:>10} {:>8} {:>8}",
+             "device", "changes", "p1", "p2", "count", "pass", "step", "brute");
+    for device in devices {
+        let lines = &device.lines;
+        let sums = match prefix_sums(lines) {
+            Ok(sums) => sums,
+            Err(overflow) => {
+                println!("{:<16} {:>8} overflow at step {}", device.label, lines.len(), overflow.step);
+                continue;
+            }
+        };
+        let total = sums.last().cloned().unwrap_or(0);
+        let repeat = first_repeat(&sums);
+        let check = match repeat {
+            Some(ref r) if brute => match first_repeat_brute(lines) {
+                Ok(ref oracle) if oracle == r => "ok",
+                Ok(_) => "MISMATCH",
+                Err(_) => "overflow",
+            },
+            _ => "-",
+        };
+        match repeat {
+            Some(ref r) => println!("{:<16} {:>8} {:>20} {:>20} {:>12} {:>10} {:>8} {:>8}",
+                                    device.label, lines.len(), total, r.freq, r.count, r.pass, r.step, check),
+            None => println!("{:<16} {:>8} {:>20} {:>20} {:>12} {:>10} {:>8} {:>8}",
+                             device.label, lines.len(), total, "never", "-", "-", "-", check),
         }
     }
+}
 
-    let mut count = 0;
-    let mut total = 0;
+// The first frequency that is reached twice, when the changes are applied
+// over and over again.  Frequency 0 at the start does not count as reached,
+// just like in the original loop.  Pass and step are zero-based, and count
+// is the number of distinct frequencies reached before the repeat.
+#[derive(Debug, PartialEq)]
+struct Repeat {
+    freq: i64,
+    pass: usize,
+    step: usize,
+    count: u128,
+}
+impl Repeat {
+    fn new(len: usize, freq: i64, pass: usize, step: usize) -> Repeat {
+        Repeat { freq, pass, step, count: pass as u128 * len as u128 + step as u128 }
+    }
+    fn print(&self, name: &str) {
+        println!("{}: {} ({}) at pass {} step {}", name, self.freq, self.count, self.pass, self.step);
+    }
+}
 
-    total = 0;
-    count = 0;
-    for num in lines.iter() {
-        total += num;
-        count += 1;
+// On pass p, step i the frequency is s[i] + p*D, where s are the prefix sums
+// of the first pass and D is the total drift.  Unless the first pass already
+// repeats itself, s[i] + p*D can only hit a previous s[j] when both have the
+// same residue modulo D; the first hit for s[i] is the closest s[j] ahead of
+// it in the direction of D, which we find by sorting each residue group.
+// Differences between frequencies are computed as i128, so this cannot
+// overflow once the first pass fits in an i64.
+fn first_repeat(sums: &[i64]) -> Option<Repeat> {
+    let len = sums.len();
+    if len == 0 {
+        return None;
     }
-    println!("p1: {} ({})", total, count);
 
-    total = 0;
-    count = 0;
     let mut seen = HashMap::new();
-    'seen: loop {
-        for num in lines.iter() {
-            total += num;
+    for (step, sum) in sums.iter().enumerate() {
+        if seen.contains_key(sum) {
+            return Some(Repeat::new(len, *sum, 0, step));
+        }
+        seen.insert(*sum, step);
+    }
+
+    let drift = sums[len - 1] as i128;
+    if drift == 0 {
+        // second pass goes over the exact same frequencies
+        return Some(Repeat::new(len, sums[0], 1, 0));
+    }
+
+    let modulo = drift.abs();
+    let mut groups: HashMap<i128, Vec<(i64, usize)>> = HashMap::new();
+    for (step, sum) in sums.iter().enumerate() {
+        groups.entry((*sum as i128).rem_euclid(modulo)).or_default().push((*sum, step));
+    }
+
+    let mut best: Option<Repeat> = None;
+    for group in groups.values_mut() {
+        group.sort();
+        if drift < 0 {
+            group.reverse();
+        }
+        for pair in group.windows(2) {
+            let (from, step) = pair[0];
+            let (to, _) = pair[1];
+            let pass = ((to as i128 - from as i128) / drift) as usize;
+            let better = match best {
+                Some(ref b) => (pass, step) < (b.pass, b.step),
+                None => true,
+            };
+            if better {
+                best = Some(Repeat::new(len, to, pass, step));
+            }
+        }
+    }
+    best
+}
+
+// Straightforward simulation; does not terminate if there is no repeat.
+fn first_repeat_brute(lines: &[i64]) -> Result<Repeat, Overflow> {
+    Ok(walk(lines, None)?.repeat.unwrap())
+}
+
+// One frequency reached while walking through the changes.
+struct Point {
+    pass: usize,
+    step: usize,
+    freq: i64,
+}
+
+// Every frequency reached up to and including the first repeat, together with
+// the index of the point where each frequency was first seen.
+struct Trajectory {
+    points: Vec<Point>,
+    seen: HashMap<i64, usize>,
+    repeat: Option<Repeat>,
+}
+
+// Apply the changes over and over until a frequency repeats, or until we are
+// done with pass max_pass, if given.
+fn walk(lines: &[i64], max_pass: Option<usize>) -> Result<Trajectory, Overflow> {
+    let mut points = Vec::new();
+    let mut seen = HashMap::new();
+    let mut total: i64 = 0;
+    let mut pass = 0;
+    while !lines.is_empty() && max_pass.is_none_or(|max| pass <= max) {
+        for (step, num) in lines.iter().enumerate() {
+            total = match total.checked_add(*num) {
+                Some(total) => total,
+                None => return Err(Overflow { pass, step }),
+            };
+            points.push(Point { pass, step, freq: total });
             if seen.contains_key(&total) {
-                break 'seen;
+                let repeat = Some(Repeat::new(lines.len(), total, pass, step));
+                return Ok(Trajectory { points, seen, repeat });
             }
-            seen.insert(total, 1);
-            count += 1;
+            seen.insert(total, points.len() - 1);
+        }
+        pass += 1;
+    }
+    Ok(Trajectory { points, seen, repeat: None })
+}
+
+impl Trajectory {
+    // Without a repeat the trajectory never ends, so we only follow the first pass.
+    fn new(lines: &[i64], repeat: &Option<Repeat>) -> Result<Trajectory, Overflow> {
+        let max_pass = match *repeat {
+            Some(ref r) => r.pass,
+            None => 0,
+        };
+        walk(lines, Some(max_pass))
+    }
+
+    fn limits(&self) -> (i64, i64) {
+        let min = self.points.iter().map(|p| p.freq).min().unwrap_or(0);
+        let max = self.points.iter().map(|p| p.freq).max().unwrap_or(0);
+        (min, max)
+    }
+
+    fn write_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "index,pass,step,freq,seen_at")?;
+        for (index, point) in self.points.iter().enumerate() {
+            let first = self.seen[&point.freq];
+            let seen_at = if first < index { first.to_string() } else { String::new() };
+            writeln!(out, "{},{},{},{},{}", index, point.pass, point.step, point.freq, seen_at)?;
+        }
+        Ok(())
+    }
+
+    fn write_json<W: Write>(&self, lines: &[i64], out: &mut W) -> io::Result<()> {
+        let drift: i64 = lines.iter().sum();
+        let (min, max) = self.limits();
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"drift\": {},", drift)?;
+        writeln!(out, "  \"min\": {},", min)?;
+        writeln!(out, "  \"max\": {},", max)?;
+        writeln!(out, "  \"distinct\": {},", self.seen.len())?;
+        match self.repeat {
+            Some(ref r) => writeln!(out, "  \"repeat\": {{ \"index\": {}, \"pass\": {}, \"step\": {}, \"freq\": {}, \"seen_at\": {} }},",
+                                    r.count, r.pass, r.step, r.freq, self.seen[&r.freq])?,
+            None => writeln!(out, "  \"repeat\": null,")?,
+        }
+        writeln!(out, "  \"points\": [")?;
+        for (index, point) in self.points.iter().enumerate() {
+            let sep = if index + 1 < self.points.len() { "," } else { "" };
+            writeln!(out, "    {{ \"index\": {}, \"pass\": {}, \"step\": {}, \"freq\": {} }}{}",
+                     index, point.pass, point.step, point.freq, sep)?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+}
+
+// Most points we are willing to write out with --csv or --json.
+const MAX_POINTS: u128 = 10_000_000;
+
+// Summary of the trajectory, derived from the first pass alone so that it
+// works even when the repeat is billions of passes away.
+struct Stats {
+    min: i128,
+    max: i128,
+    distinct: u128,
+    seen_at: Option<usize>,
+}
+impl Stats {
+    // Point (p, i) has frequency s[i] + p*D; for each step this is linear in
+    // p, so its extremes are at the first and last pass that reaches it.
+    fn new(sums: &[i64], repeat: &Option<Repeat>) -> Stats {
+        let drift = sums.last().cloned().unwrap_or(0) as i128;
+        let (mut min, mut max) = (0, 0);
+        for (step, sum) in sums.iter().enumerate() {
+            let last_pass = match *repeat {
+                Some(ref r) if step <= r.step => r.pass,
+                Some(ref r) if r.pass > 0 => r.pass - 1,
+                Some(_) => break,
+                None => 0,
+            };
+            let (first, last) = (*sum as i128, *sum as i128 + last_pass as i128 * drift);
+            if step == 0 {
+                min = first.min(last);
+                max = first.max(last);
+            }
+            min = min.min(first).min(last);
+            max = max.max(first).max(last);
+        }
+        // the repeated frequency always comes from the first pass
+        let (distinct, seen_at) = match *repeat {
+            Some(ref r) => (r.count, sums.iter().position(|sum| *sum == r.freq)),
+            None => (sums.len() as u128, None),
+        };
+        Stats { min, max, distinct, seen_at }
+    }
+
+    fn print(&self, lines: &[i64], repeat: &Option<Repeat>) {
+        let drift: i64 = lines.iter().sum();
+        println!("stats: min {} max {} drift {} per pass, {} distinct", self.min, self.max, drift, self.distinct);
+        match (repeat, self.seen_at) {
+            (Some(r), Some(seen_at)) => println!("stats: first repeat at index {} (first seen at index {})", r.count, seen_at),
+            _ => println!("stats: no repeat"),
+        }
+    }
+}
+
+fn export(lines: &[i64], sums: &[i64], repeat: &Option<Repeat>, stats: bool, csv: &Option<String>, json: &Option<String>) {
+    if stats {
+        Stats::new(sums, repeat).print(lines, repeat);
+    }
+    if csv.is_none() && json.is_none() {
+        return;
+    }
+    let points = match *repeat {
+        Some(ref r) => r.count + 1,
+        None => lines.len() as u128,
+    };
+    if points > MAX_POINTS {
+        eprintln!("the trajectory has {} points, too many to write out (at most {})", points, MAX_POINTS);
+        std::process::exit(1);
+    }
+    let trajectory = match Trajectory::new(lines, repeat) {
+        Ok(trajectory) => trajectory,
+        Err(overflow) => {
+            overflow.print("trajectory");
+            std::process::exit(1);
+        }
+    };
+    if let Some(ref name) = *csv {
+        let mut out = BufWriter::new(create_file(name));
+        trajectory.write_csv(&mut out).unwrap();
+    }
+    if let Some(ref name) = *json {
+        let mut out = BufWriter::new(create_file(name));
+        trajectory.write_json(lines, &mut out).unwrap();
+    }
+}
+
+fn create_file(name: &str) -> File {
+    match File::create(name) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            std::process::exit(1);
         }
     }
-    println!("p2: {} ({})", total, count);
 }
@@ -1,82 +1,297 @@
+use std::env;
 use std::io::{self, BufRead};
+use std::collections::{HashMap, HashSet};
 
 fn main() {
+    let mut distance = 1;
+    let mut multiplicities = vec![2, 3];
+    let mut sum = false;
+    let mut families = None;
+    for arg in env::args().skip(1) {
+        if let Some(value) = arg.strip_prefix("--distance=") {
+            distance = parse_count(value, "distance");
+        } else if let Some(value) = arg.strip_prefix("--counts=") {
+            if value.trim().is_empty() {
+                eprintln!("--counts needs a list such as 2,3");
+                std::process::exit(1);
+            }
+            multiplicities = value.split(',').map(|v| parse_count(v, "count")).collect();
+        } else if let Some(value) = arg.strip_prefix("--families=") {
+            families = Some(parse_count(value, "edit distance"));
+        } else if arg == "--sum" {
+            sum = true;
+        } else {
+            eprintln!("{}: unknown argument", arg);
+            std::process::exit(1);
+        }
+    }
+
     let mut raw = Vec::new();
-    let mut sorted = Vec::new();
     let stdin = io::stdin();
 
     for line in stdin.lock().lines() {
-        let mut chars: Vec<char> = line.unwrap().chars().collect();
-        let mut chars_sorted = chars.clone();
+        let chars: Vec<char> = line.unwrap().chars().collect();
         raw.push(chars);
-        chars_sorted.sort();
-        sorted.push(chars_sorted);
     }
 
-    part1(&sorted);
-    part2(&raw);
+    part1(&raw, &multiplicities, sum);
+    part2(&raw, distance);
+    if let Some(edits) = families {
+        find_families(&raw, edits);
+    }
+}
+
+fn parse_count(value: &str, what: &str) -> usize {
+    match value.trim().parse::<usize>() {
+        Ok(count) => count,
+        Err(_) => {
+            eprintln!("{}: bad {}, expected a number", value, what);
+            std::process::exit(1);
+        }
+    }
+}
+
+// How many times each letter appears in an id.
+fn histogram(chars: &[char]) -> HashMap<char, usize> {
+    let mut histogram = HashMap::new();
+    for c in chars {
+        *histogram.entry(*c).or_insert(0) += 1;
+    }
+    histogram
 }
 
-fn part1(lines: &Vec<Vec<char>>) {
-    let mut count2 = 0;
-    let mut count3 = 0;
+// For each multiplicity k, the number of ids that contain some letter exactly k times.
+fn count_multiplicities(lines: &[Vec<char>], multiplicities: &[usize]) -> Vec<usize> {
+    let mut counts = vec![0; multiplicities.len()];
     for chars in lines {
-        let mut last = '\u{0000}';
-        let mut count = 0;
-        let mut c2 = 0;
-        let mut c3 = 0;
-        for curr in chars {
-            if last != *curr {
-                last = *curr;
-                count = 1;
-                continue;
+        let present: HashSet<usize> = histogram(chars).values().cloned().collect();
+        for (pos, k) in multiplicities.iter().enumerate() {
+            if present.contains(k) {
+                counts[pos] += 1;
             }
-            count += 1;
-            if count == 2 {
-                c2 += 1;
+        }
+    }
+    counts
+}
+
+// The checksum is the product of the counts, or their sum if so requested.
+fn part1(lines: &[Vec<char>], multiplicities: &[usize], sum: bool) {
+    let counts = count_multiplicities(lines, multiplicities);
+    let (op, checksum) = if sum {
+        (" + ", counts.iter().sum::<usize>())
+    } else {
+        (" * ", counts.iter().product::<usize>())
+    };
+    let terms: Vec<String> = counts.iter().map(|c| c.to_string()).collect();
+    println!("{} = {}", terms.join(op), checksum);
+}
+
+fn part2(lines: &[Vec<char>], distance: usize) {
+    for (jpos, kpos) in close_pairs(lines, distance) {
+        let mut diffs = Vec::new();
+        let mut common: Vec<char> = Vec::new();
+        for (cpos, (a, b)) in lines[jpos].iter().zip(lines[kpos].iter()).enumerate() {
+            if a == b {
+                common.push(*a);
+            } else {
+                diffs.push(cpos);
             }
-            if count == 3 {
-                c2 -= 1;
-                c3 += 1;
+        }
+        let common: String = common.into_iter().collect();
+        let first: String = lines[jpos].iter().collect();
+        let second: String = lines[kpos].iter().collect();
+        println!("{} {} differ at {:?} => {}", first, second, diffs, common);
+    }
+}
+
+// Find all pairs of ids with the same length that differ in at most distance
+// positions.  Each id is indexed under every way of masking distance of its
+// positions; two ids that are close enough share at least one of those keys,
+// so we only need to compare ids within the same bucket.  Pairs come back
+// sorted and without duplicates.
+fn close_pairs(lines: &[Vec<char>], distance: usize) -> Vec<(usize, usize)> {
+    let mut buckets: HashMap<Vec<Option<char>>, Vec<usize>> = HashMap::new();
+    let mut masks: HashMap<usize, Vec<Vec<usize>>> = HashMap::new();
+    for (pos, chars) in lines.iter().enumerate() {
+        let len = chars.len();
+        let masks = masks.entry(len).or_insert_with(|| combinations(len, distance.min(len)));
+        for mask in masks.iter() {
+            let mut key: Vec<Option<char>> = chars.iter().map(|c| Some(*c)).collect();
+            for m in mask {
+                key[*m] = None;
             }
-            if count == 4 {
-                c3 -= 1;
+            buckets.entry(key).or_default().push(pos);
+        }
+    }
+
+    let mut pairs = HashSet::new();
+    for bucket in buckets.values() {
+        for (j, jpos) in bucket.iter().enumerate() {
+            for kpos in &bucket[j+1..] {
+                pairs.insert((*jpos, *kpos));
             }
         }
-        // println!("{} => {} {}", line, c2, c3);
-        if c2 > 0 {
-            count2 += 1;
+    }
+    let mut pairs: Vec<(usize, usize)> = pairs.into_iter().collect();
+    pairs.sort();
+    pairs
+}
+
+// All the ways to choose k positions out of n, in lexicographic order.
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    let mut all = Vec::new();
+    let mut current: Vec<usize> = (0..k).collect();
+    loop {
+        all.push(current.clone());
+        // find the rightmost position that can still move forward
+        let mut pos = k;
+        while pos > 0 && current[pos-1] == n - k + pos - 1 {
+            pos -= 1;
         }
-        if c3 > 0 {
-            count3 += 1;
+        if pos == 0 {
+            break;
+        }
+        current[pos-1] += 1;
+        for j in pos..k {
+            current[j] = current[j-1] + 1;
         }
     }
-    println!("{} * {} = {}", count2, count3, count2 * count3);
+    all
+}
 
+// Edit distance between two ids, or None if it is larger than limit.
+fn levenshtein(a: &[char], b: &[char], limit: usize) -> Option<usize> {
+    let (la, lb) = (a.len(), b.len());
+    if la.max(lb) - la.min(lb) > limit {
+        return None;
+    }
+    let mut prev: Vec<usize> = (0..lb+1).collect();
+    let mut curr = vec![0; lb+1];
+    for j in 1..la+1 {
+        curr[0] = j;
+        let mut best = curr[0];
+        for k in 1..lb+1 {
+            let cost = if a[j-1] == b[k-1] { 0 } else { 1 };
+            curr[k] = (prev[k-1] + cost).min(prev[k] + 1).min(curr[k-1] + 1);
+            best = best.min(curr[k]);
+        }
+        if best > limit {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    if prev[lb] > limit { None } else { Some(prev[lb]) }
+}
+
+// Disjoint sets of ids, merged as we find close pairs.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+impl UnionFind {
+    fn new(count: usize) -> UnionFind {
+        UnionFind { parent: (0..count).collect(), size: vec![1; count] }
+    }
+    fn find(&mut self, pos: usize) -> usize {
+        let mut root = pos;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut pos = pos;
+        while self.parent[pos] != root {
+            let next = self.parent[pos];
+            self.parent[pos] = root;
+            pos = next;
+        }
+        root
+    }
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut ra, mut rb) = (self.find(a), self.find(b));
+        if ra == rb {
+            return;
+        }
+        if self.size[ra] < self.size[rb] {
+            std::mem::swap(&mut ra, &mut rb);
+        }
+        self.parent[rb] = ra;
+        self.size[ra] += self.size[rb];
+    }
+}
+
+// A group of ids connected through chains of ids at most edits apart.
+struct Family {
+    members: Vec<usize>,
+    consensus: Vec<char>,
+    agreement: Vec<f64>,
+    outliers: Vec<usize>,
 }
+impl Family {
+    // The consensus is the most common letter at each position, taken over
+    // the members with the most common length; members further than edits
+    // away from the consensus are outliers.
+    fn new(lines: &[Vec<char>], members: Vec<usize>, edits: usize) -> Family {
+        let mut lengths: HashMap<usize, usize> = HashMap::new();
+        for m in &members {
+            *lengths.entry(lines[*m].len()).or_insert(0) += 1;
+        }
+        let (len, _) = lengths.into_iter().max_by_key(|&(len, count)| (count, std::cmp::Reverse(len))).unwrap();
+        let same: Vec<&Vec<char>> = members.iter().map(|m| &lines[*m]).filter(|l| l.len() == len).collect();
+
+        let mut consensus = Vec::with_capacity(len);
+        let mut agreement = Vec::with_capacity(len);
+        for cpos in 0..len {
+            let mut votes: HashMap<char, usize> = HashMap::new();
+            for chars in &same {
+                *votes.entry(chars[cpos]).or_insert(0) += 1;
+            }
+            let (c, count) = votes.into_iter().max_by_key(|&(c, count)| (count, std::cmp::Reverse(c))).unwrap();
+            consensus.push(c);
+            agreement.push(count as f64 / same.len() as f64);
+        }
 
-fn part2(lines: &Vec<Vec<char>>) {
+        let outliers = members.iter().cloned()
+            .filter(|m| levenshtein(&lines[*m], &consensus, edits).is_none())
+            .collect();
+        Family { members, consensus, agreement, outliers }
+    }
+
+    fn print(&self, lines: &[Vec<char>], pos: usize) {
+        let consensus: String = self.consensus.iter().collect();
+        let agreement: Vec<String> = self.agreement.iter().map(|a| format!("{:.0}", a * 100.0)).collect();
+        println!("family {}: {} ids", pos + 1, self.members.len());
+        println!("  consensus {}", consensus);
+        println!("  agreement {}", agreement.join(" "));
+        for m in &self.members {
+            let chars: String = lines[*m].iter().collect();
+            let mark = if self.outliers.contains(m) { " (outlier)" } else { "" };
+            println!("  {}{}", chars, mark);
+        }
+    }
+}
+
+// Group all ids into families whose members are connected by at most edits
+// insertions, deletions or substitutions, comparing every pair like part2.
+fn find_families(lines: &[Vec<char>], edits: usize) {
+    let mut sets = UnionFind::new(lines.len());
     for jpos in 0..lines.len() {
         for kpos in (jpos+1)..lines.len() {
-            let mut diff = 0;
-            let mut common: Vec<char> = Vec::new();
-            for cpos in 0..lines[jpos].len() {
-                if lines[jpos][cpos] == lines[kpos][cpos] {
-                    common.push(lines[jpos][cpos]);
-                    continue;
-                }
-                diff += 1;
-                if diff > 1 {
-                    break;
-                }
+            if levenshtein(&lines[jpos], &lines[kpos], edits).is_some() {
+                sets.union(jpos, kpos);
             }
-            // println!("{} {} {} {:?} {:?}", diff, jpos, kpos, lines[jpos], lines[kpos]);
-            if diff > 1 {
-                continue;
-            }
-            let common: String = common.into_iter().collect();
-            // println!("=> {} {:?} {:?}", diff, lines[jpos], lines[kpos]);
-            println!("{}", common);
         }
     }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for pos in 0..lines.len() {
+        let root = sets.find(pos);
+        groups.entry(root).or_default().push(pos);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().filter(|g| g.len() > 1).collect();
+    groups.sort();
+
+    let singles = lines.len() - groups.iter().map(|g| g.len()).sum::<usize>();
+    println!("{} families, {} ids on their own", groups.len(), singles);
+    for (pos, members) in groups.into_iter().enumerate() {
+        Family::new(lines, members, edits).print(lines, pos);
+    }
 }
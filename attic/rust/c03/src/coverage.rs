@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use super::Claim;
+
+// Coverage of the fabric computed over compressed coordinates: the claim
+// edges split the fabric into at most (2n+1)^2 cells, and every inch inside
+// a cell is covered by the same number of claims.  Cost depends only on the
+// number of claims, not on the size of the fabric.
+pub struct Coverage {
+    xs: Vec<i64>,
+    ys: Vec<i64>,
+    depth: Vec<u32>,
+    bad: Vec<u32>,
+}
+impl Coverage {
+    pub fn new(claims: &[Claim]) -> Coverage {
+        let xs = edges(claims.iter().flat_map(|c| vec![c.x as i64, c.x as i64 + c.w as i64]));
+        let ys = edges(claims.iter().flat_map(|c| vec![c.y as i64, c.y as i64 + c.h as i64]));
+        let (nx, ny) = (xs.len(), ys.len());
+
+        // 2D difference array over the edges, summed up into cell depths
+        let mut diff = vec![0i32; nx * ny];
+        for claim in claims {
+            let (x0, x1, y0, y1) = cell_range(&xs, &ys, claim);
+            diff[x0 * ny + y0] += 1;
+            diff[x1 * ny + y0] -= 1;
+            diff[x0 * ny + y1] -= 1;
+            diff[x1 * ny + y1] += 1;
+        }
+        for x in 0..nx {
+            for y in 1..ny {
+                diff[x * ny + y] += diff[x * ny + y - 1];
+            }
+        }
+        for x in 1..nx {
+            for y in 0..ny {
+                diff[x * ny + y] += diff[(x - 1) * ny + y];
+            }
+        }
+        let depth: Vec<u32> = diff.into_iter().map(|d| d as u32).collect();
+
+        // prefix sums of cells covered more than once, so that we can
+        // check any claim in constant time
+        let mut bad = vec![0u32; (nx + 1) * (ny + 1)];
+        for x in 0..nx {
+            for y in 0..ny {
+                let here = if depth[x * ny + y] > 1 { 1 } else { 0 };
+                bad[(x + 1) * (ny + 1) + y + 1] = here
+                    + bad[x * (ny + 1) + y + 1]
+                    + bad[(x + 1) * (ny + 1) + y]
+                    - bad[x * (ny + 1) + y];
+            }
+        }
+
+        Coverage { xs, ys, depth, bad }
+    }
+
+    // Area covered by exactly k claims, for every k > 0 that occurs.
+    pub fn histogram(&self) -> BTreeMap<u32, u64> {
+        let mut histogram = BTreeMap::new();
+        let ny = self.ys.len();
+        for x in 0..self.xs.len().saturating_sub(1) {
+            for y in 0..ny.saturating_sub(1) {
+                let depth = self.depth[x * ny + y];
+                if depth == 0 {
+                    continue;
+                }
+                let area = (self.xs[x + 1] - self.xs[x]) as u64 * (self.ys[y + 1] - self.ys[y]) as u64;
+                *histogram.entry(depth).or_insert(0) += area;
+            }
+        }
+        histogram
+    }
+
+    // Area covered by at least two claims.
+    pub fn overlapped(&self) -> u64 {
+        self.histogram().iter().filter(|&(depth, _)| *depth > 1).map(|(_, area)| area).sum()
+    }
+
+    // Whether no inch of this claim is shared with another claim.
+    pub fn is_intact(&self, claim: &Claim) -> bool {
+        let (x0, x1, y0, y1) = cell_range(&self.xs, &self.ys, claim);
+        let ny = self.ys.len() + 1;
+        let shared = self.bad[x1 * ny + y1] + self.bad[x0 * ny + y0]
+            - self.bad[x0 * ny + y1] - self.bad[x1 * ny + y0];
+        shared == 0
+    }
+}
+
+fn edges<I: Iterator<Item = i64>>(values: I) -> Vec<i64> {
+    let mut edges: Vec<i64> = values.collect();
+    edges.sort();
+    edges.dedup();
+    edges
+}
+
+// Range of cells [x0, x1) x [y0, y1) covered by a claim.
+fn cell_range(xs: &[i64], ys: &[i64], claim: &Claim) -> (usize, usize, usize, usize) {
+    let find = |edges: &[i64], value: i64| edges.binary_search(&value).unwrap();
+    (find(xs, claim.x as i64), find(xs, claim.x as i64 + claim.w as i64),
+     find(ys, claim.y as i64), find(ys, claim.y as i64 + claim.h as i64))
+}
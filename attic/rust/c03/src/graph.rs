@@ -0,0 +1,141 @@
+use std::io::{self, Write};
+
+use super::Claim;
+
+// Claims that share some fabric are connected by an edge, weighted with the
+// shared area.  Nodes are positions in the claims slice.
+pub struct Graph {
+    edges: Vec<(usize, usize, u64)>,
+    neighbours: Vec<Vec<usize>>,
+}
+impl Graph {
+    pub fn new(claims: &[Claim]) -> Graph {
+        // sweep claims by their left edge; once a claim starts to the right
+        // of the current one, no later claim can overlap it either
+        let mut order: Vec<usize> = (0..claims.len()).collect();
+        order.sort_by_key(|&pos| claims[pos].x);
+
+        let mut edges = Vec::new();
+        let mut neighbours = vec![Vec::new(); claims.len()];
+        for (j, &a) in order.iter().enumerate() {
+            let right = claims[a].x as i64 + claims[a].w as i64;
+            for &b in &order[j+1..] {
+                if claims[b].x as i64 >= right {
+                    break;
+                }
+                let area = shared_area(&claims[a], &claims[b]);
+                if area == 0 {
+                    continue;
+                }
+                let (a, b) = if a < b { (a, b) } else { (b, a) };
+                edges.push((a, b, area));
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        }
+        edges.sort();
+        for list in neighbours.iter_mut() {
+            list.sort();
+        }
+        Graph { edges, neighbours }
+    }
+
+    pub fn degree(&self, pos: usize) -> usize {
+        self.neighbours[pos].len()
+    }
+
+    // Groups of claims connected through shared fabric, largest first.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; self.neighbours.len()];
+        let mut components = Vec::new();
+        for start in 0..self.neighbours.len() {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut component = vec![start];
+            let mut pending = vec![start];
+            while let Some(pos) = pending.pop() {
+                for &next in &self.neighbours[pos] {
+                    if !seen[next] {
+                        seen[next] = true;
+                        component.push(next);
+                        pending.push(next);
+                    }
+                }
+            }
+            component.sort();
+            components.push(component);
+        }
+        components.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        components
+    }
+
+    pub fn print(&self, claims: &[Claim]) {
+        println!("{} conflicts", self.edges.len());
+        let mut shared = vec![0u64; claims.len()];
+        for &(a, b, area) in &self.edges {
+            shared[a] += area;
+            shared[b] += area;
+        }
+        for (pos, claim) in claims.iter().enumerate() {
+            println!("#{} degree {} shared {}", claim.id, self.degree(pos), shared[pos]);
+        }
+        let components = self.components();
+        let singles = components.iter().filter(|c| c.len() == 1).count();
+        println!("{} components, {} of them a single claim", components.len(), singles);
+        for component in components.iter().filter(|c| c.len() > 1) {
+            let ids: Vec<String> = component.iter().map(|&pos| format!("#{}", claims[pos].id)).collect();
+            println!("component of {}: {}", component.len(), ids.join(" "));
+        }
+    }
+
+    pub fn write_dot<W: Write>(&self, claims: &[Claim], out: &mut W) -> io::Result<()> {
+        writeln!(out, "graph claims {{")?;
+        for (pos, claim) in claims.iter().enumerate() {
+            let style = if self.degree(pos) == 0 { ", style=filled, fillcolor=green" } else { "" };
+            writeln!(out, "  c{} [label=\"#{}\"{}];", claim.id, claim.id, style)?;
+        }
+        for &(a, b, area) in &self.edges {
+            writeln!(out, "  c{} -- c{} [weight={}, label=\"{}\"];", claims[a].id, claims[b].id, area, area)?;
+        }
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, claims: &[Claim], out: &mut W) -> io::Result<()> {
+        let components = self.components();
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"nodes\": [")?;
+        for (pos, claim) in claims.iter().enumerate() {
+            let sep = if pos + 1 < claims.len() { "," } else { "" };
+            writeln!(out, "    {{ \"id\": {}, \"degree\": {} }}{}", claim.id, self.degree(pos), sep)?;
+        }
+        writeln!(out, "  ],")?;
+        writeln!(out, "  \"edges\": [")?;
+        for (pos, &(a, b, area)) in self.edges.iter().enumerate() {
+            let sep = if pos + 1 < self.edges.len() { "," } else { "" };
+            writeln!(out, "    {{ \"a\": {}, \"b\": {}, \"area\": {} }}{}", claims[a].id, claims[b].id, area, sep)?;
+        }
+        writeln!(out, "  ],")?;
+        writeln!(out, "  \"components\": [")?;
+        for (pos, component) in components.iter().enumerate() {
+            let sep = if pos + 1 < components.len() { "," } else { "" };
+            let ids: Vec<String> = component.iter().map(|&c| claims[c].id.to_string()).collect();
+            writeln!(out, "    [{}]{}", ids.join(", "), sep)?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+}
+
+// Number of square inches shared by two claims.
+pub fn shared_area(a: &Claim, b: &Claim) -> u64 {
+    let w = (a.x + a.w).min(b.x + b.w) - a.x.max(b.x);
+    let h = (a.y + a.h).min(b.y + b.h) - a.y.max(b.y);
+    if w <= 0 || h <= 0 {
+        return 0;
+    }
+    w as u64 * h as u64
+}
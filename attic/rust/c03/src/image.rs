@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use super::Claim;
+
+// Part of the fabric to render.
+pub struct Region {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+impl Region {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Region {
+        Region { x, y, w, h }
+    }
+
+    // The whole fabric from (0, 0) up to the farthest claim edge.
+    pub fn of_claims(claims: &[Claim]) -> Region {
+        let w = claims.iter().map(|c| c.x + c.w).max().unwrap_or(0);
+        let h = claims.iter().map(|c| c.y + c.h).max().unwrap_or(0);
+        Region { x: 0, y: 0, w, h }
+    }
+
+    // Parse a region given as "X,Y:WxH"; it must not be empty, start before
+    // the origin or reach past the largest coordinate.
+    pub fn parse(text: &str) -> Option<Region> {
+        let (pos, size) = text.split_once(':')?;
+        let (x, y) = pos.trim().split_once(',')?;
+        let (w, h) = size.trim().split_once('x')?;
+        let region = Region::new(x.trim().parse().ok()?, y.trim().parse().ok()?,
+                                 w.trim().parse().ok()?, h.trim().parse().ok()?);
+        if region.x < 0 || region.y < 0 || region.w <= 0 || region.h <= 0 {
+            return None;
+        }
+        if region.x.checked_add(region.w).is_none() || region.y.checked_add(region.h).is_none() {
+            return None;
+        }
+        Some(region)
+    }
+}
+
+fn max_count(grid: &HashMap<(i32, i32), u32>) -> u32 {
+    grid.values().cloned().max().unwrap_or(0).max(1)
+}
+
+fn gray(grid: &HashMap<(i32, i32), u32>, max: u32, x: i32, y: i32) -> u8 {
+    let count = grid.get(&(x, y)).cloned().unwrap_or(0);
+    (count * 255 / max) as u8
+}
+
+// Binary greyscale image, where brighter means more claims over that inch.
+pub fn write_pgm<W: Write>(grid: &HashMap<(i32, i32), u32>, region: &Region, out: &mut W) -> io::Result<()> {
+    let max = max_count(grid);
+    writeln!(out, "P5")?;
+    writeln!(out, "# coverage, white is {} claims", max)?;
+    writeln!(out, "{} {}", region.w, region.h)?;
+    writeln!(out, "255")?;
+    for y in region.y..region.y + region.h {
+        let row: Vec<u8> = (region.x..region.x + region.w).map(|x| gray(grid, max, x, y)).collect();
+        out.write_all(&row)?;
+    }
+    Ok(())
+}
+
+// Binary colour image with the same coverage in grey, and the outline of
+// every intact claim drawn in green.
+pub fn write_ppm<W: Write>(grid: &HashMap<(i32, i32), u32>, good: &[&Claim], region: &Region, out: &mut W) -> io::Result<()> {
+    let max = max_count(grid);
+    let mut outline = HashMap::new();
+    for claim in good {
+        for x in claim.x..claim.x + claim.w {
+            for y in claim.y..claim.y + claim.h {
+                if x == claim.x || y == claim.y || x == claim.x + claim.w - 1 || y == claim.y + claim.h - 1 {
+                    outline.insert((x, y), true);
+                }
+            }
+        }
+    }
+
+    writeln!(out, "P6")?;
+    writeln!(out, "{} {}", region.w, region.h)?;
+    writeln!(out, "255")?;
+    for y in region.y..region.y + region.h {
+        let mut row = Vec::with_capacity(region.w as usize * 3);
+        for x in region.x..region.x + region.w {
+            if outline.contains_key(&(x, y)) {
+                row.extend_from_slice(&[0, 255, 0]);
+            } else {
+                let g = gray(grid, max, x, y);
+                row.extend_from_slice(&[g, g, g]);
+            }
+        }
+        out.write_all(&row)?;
+    }
+    Ok(())
+}
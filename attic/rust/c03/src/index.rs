@@ -0,0 +1,105 @@
+use super::Claim;
+
+// Maximum number of children of a node in the tree.
+const FANOUT: usize = 8;
+
+// Half-open rectangle [x0, x1) x [y0, y1) of fabric.
+#[derive(Copy, Clone)]
+pub struct Rect {
+    x0: i64,
+    y0: i64,
+    x1: i64,
+    y1: i64,
+}
+impl Rect {
+    // None if the far edge is out of range.
+    pub fn new(x: i64, y: i64, w: i64, h: i64) -> Option<Rect> {
+        Some(Rect { x0: x, y0: y, x1: x.checked_add(w)?, y1: y.checked_add(h)? })
+    }
+    fn of_claim(claim: &Claim) -> Rect {
+        // claims are i32, so this always fits
+        Rect::new(claim.x as i64, claim.y as i64, claim.w as i64, claim.h as i64).unwrap()
+    }
+    fn intersects(&self, other: &Rect) -> bool {
+        self.x0 < other.x1 && other.x0 < self.x1 && self.y0 < other.y1 && other.y0 < self.y1
+    }
+    fn union(&self, other: &Rect) -> Rect {
+        Rect { x0: self.x0.min(other.x0), y0: self.y0.min(other.y0),
+               x1: self.x1.max(other.x1), y1: self.y1.max(other.y1) }
+    }
+    fn center(&self) -> (i64, i64) {
+        (self.x0 + self.x1, self.y0 + self.y1)
+    }
+}
+
+struct Node {
+    bounds: Rect,
+    kind: Kind,
+}
+enum Kind {
+    Leaf(usize),
+    Inner(Vec<Node>),
+}
+
+// Static R-tree over the claims, bulk loaded with sort-tile-recursive packing.
+pub struct Index {
+    root: Option<Node>,
+}
+impl Index {
+    pub fn new(claims: &[Claim]) -> Index {
+        let mut level: Vec<Node> = claims.iter().enumerate()
+            .map(|(pos, claim)| Node { bounds: Rect::of_claim(claim), kind: Kind::Leaf(pos) })
+            .collect();
+        while level.len() > 1 {
+            level = pack(level);
+        }
+        Index { root: level.pop() }
+    }
+
+    // Positions of all claims that cover some of the given rectangle.
+    pub fn search(&self, rect: &Rect) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut pending: Vec<&Node> = self.root.iter().collect();
+        while let Some(node) = pending.pop() {
+            if !node.bounds.intersects(rect) {
+                continue;
+            }
+            match node.kind {
+                Kind::Leaf(pos) => found.push(pos),
+                Kind::Inner(ref children) => pending.extend(children.iter()),
+            }
+        }
+        found.sort();
+        found
+    }
+
+    // Positions of all claims that cover inch (x, y).
+    pub fn search_point(&self, x: i64, y: i64) -> Option<Vec<usize>> {
+        Rect::new(x, y, 1, 1).map(|rect| self.search(&rect))
+    }
+}
+
+// Group nodes into parents: sort by x into vertical slabs, sort each slab by
+// y and cut it into runs of FANOUT nodes.
+fn pack(mut nodes: Vec<Node>) -> Vec<Node> {
+    let parents = nodes.len().div_ceil(FANOUT);
+    let slabs = (parents as f64).sqrt().ceil() as usize;
+    let per_slab = slabs * FANOUT;
+
+    nodes.sort_by_key(|n| n.bounds.center().0);
+    let mut packed = Vec::with_capacity(parents);
+    while !nodes.is_empty() {
+        let rest = nodes.split_off(per_slab.min(nodes.len()));
+        let mut slab = nodes;
+        nodes = rest;
+        slab.sort_by_key(|n| n.bounds.center().1);
+        while !slab.is_empty() {
+            let rest = slab.split_off(FANOUT.min(slab.len()));
+            let children = slab;
+            slab = rest;
+            let bounds = children.iter().skip(1).fold(children[0].bounds, |b, n| b.union(&n.bounds));
+            packed.push(Node { bounds, kind: Kind::Inner(children) });
+        }
+    }
+    packed
+}
@@ -1,9 +1,27 @@
 extern crate regex;
+extern crate failure;
+
+mod coverage;
+mod graph;
+mod image;
+mod index;
+mod session;
+mod sheet;
 
 use std::collections::HashMap;
-use std::io::{self, BufRead};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter};
+use failure::Fail;
 use regex::Regex;
+use coverage::Coverage;
+use graph::Graph;
+use image::Region;
+use index::{Index, Rect};
+use session::Session;
+use sheet::Sheet;
 
+#[derive(Clone)]
 struct Claim {
     id: i32,
     x: i32,
@@ -13,19 +31,199 @@ struct Claim {
 }
 
 fn main() {
-    let claims = read_claims();
+    let mut sweep = false;
+    let mut graph = false;
+    let mut dot = None;
+    let mut json = None;
+    let mut input = None;
+    let mut query = None;
+    let mut pgm = None;
+    let mut ppm = None;
+    let mut crop = None;
+    let mut keep_going = false;
+    let mut sheet = None;
+    let mut session = None;
+    for arg in env::args().skip(1) {
+        if arg == "--sweep" {
+            sweep = true;
+        } else if arg == "--keep-going" {
+            keep_going = true;
+        } else if arg == "--graph" {
+            graph = true;
+        } else if let Some(name) = arg.strip_prefix("--dot=") {
+            dot = Some(name.to_string());
+        } else if let Some(name) = arg.strip_prefix("--json=") {
+            json = Some(name.to_string());
+        } else if let Some(name) = arg.strip_prefix("--claims=") {
+            input = Some(name.to_string());
+        // queries come from stdin, unless a file is given
+        } else if arg == "--query" {
+            query = Some("-".to_string());
+        } else if let Some(name) = arg.strip_prefix("--query=") {
+            query = Some(name.to_string());
+        // same for session commands
+        } else if arg == "--session" {
+            session = Some("-".to_string());
+        } else if let Some(name) = arg.strip_prefix("--session=") {
+            session = Some(name.to_string());
+        } else if let Some(name) = arg.strip_prefix("--pgm=") {
+            pgm = Some(name.to_string());
+        } else if let Some(name) = arg.strip_prefix("--ppm=") {
+            ppm = Some(name.to_string());
+        } else if arg == "--sheet" {
+            sheet = Some(Sheet::new(1000, 1000));
+        } else if let Some(text) = arg.strip_prefix("--sheet=") {
+            match Sheet::parse(text) {
+                Some(size) => sheet = Some(size),
+                None => {
+                    eprintln!("{}: bad sheet size, expected WxH", text);
+                    std::process::exit(1);
+                }
+            }
+        } else if let Some(text) = arg.strip_prefix("--crop=") {
+            match Region::parse(text) {
+                Some(region) => crop = Some(region),
+                None => {
+                    eprintln!("{}: bad region, expected X,Y:WxH", text);
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            eprintln!("{}: unknown argument", arg);
+            std::process::exit(1);
+        }
+    }
+
+    // only one mode runs; the sheet check and images belong to the default one
+    let modes: Vec<&str> = [
+        ("--query", query.is_some()),
+        ("--graph, --dot or --json", graph || dot.is_some() || json.is_some()),
+        ("--sweep", sweep),
+        ("--session", session.is_some()),
+    ].iter().filter(|mode| mode.1).map(|mode| mode.0).collect();
+    let extras: Vec<&str> = [
+        ("--sheet", sheet.is_some()),
+        ("--pgm", pgm.is_some()),
+        ("--ppm", ppm.is_some()),
+        ("--crop", crop.is_some()),
+    ].iter().filter(|extra| extra.1).map(|extra| extra.0).collect();
+    if modes.len() > 1 {
+        eprintln!("{} cannot be combined with {}", modes[0], modes[1]);
+        std::process::exit(1);
+    }
+    if let (Some(mode), Some(extra)) = (modes.first(), extras.first()) {
+        eprintln!("{} cannot be combined with {}", extra, mode);
+        std::process::exit(1);
+    }
+    let stdin_name = Some("-".to_string());
+    if input.is_none() && (query == stdin_name || session == stdin_name) {
+        eprintln!("--query and --session read stdin, so claims must come from --claims=FILE");
+        std::process::exit(1);
+    }
+
+    let (claims, errors) = match input {
+        Some(ref name) => read_claims(BufReader::new(open_file(name))),
+        None => {
+            let stdin = io::stdin();
+            read_claims(stdin.lock())
+        }
+    };
+    for e in &errors {
+        eprintln!("{}", e);
+    }
+    if !errors.is_empty() {
+        if !keep_going {
+            eprintln!("{} bad lines, use --keep-going to continue with the {} valid claims", errors.len(), claims.len());
+            std::process::exit(1);
+        }
+        eprintln!("{} bad lines skipped", errors.len());
+    }
     println!("{} claims", claims.len());
 
+    if let Some(ref name) = query {
+        let index = Index::new(&claims);
+        if name == "-" {
+            let stdin = io::stdin();
+            run_queries(&claims, &index, stdin.lock());
+        } else {
+            run_queries(&claims, &index, BufReader::new(open_file(name)));
+        }
+        return;
+    }
+
+    if graph || dot.is_some() || json.is_some() {
+        report_conflicts(&claims, graph, &dot, &json);
+        return;
+    }
+    if sweep {
+        sweep_claims(&claims);
+        return;
+    }
+
     let grid = fill_grid(&claims);
     println!("{} squares", grid.len());
 
+    if let Some(ref name) = session {
+        let mut session = match Session::new(claims, grid) {
+            Ok(session) => session,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        if name == "-" {
+            let stdin = io::stdin();
+            session.run(stdin.lock());
+        } else {
+            session.run(BufReader::new(open_file(name)));
+        }
+        return;
+    }
+
     find_bad_squares(&grid);
-    find_good_claims(&claims, &grid);
+    let good = find_good_claims(&claims, &grid);
+
+    if let Some(ref sheet) = sheet {
+        check_sheet(&claims, &grid, sheet);
+    }
+
+    let region = crop.unwrap_or_else(|| Region::of_claims(&claims));
+    if let Some(ref name) = pgm {
+        let mut out = BufWriter::new(create_file(name));
+        image::write_pgm(&grid, &region, &mut out).unwrap();
+    }
+    if let Some(ref name) = ppm {
+        let mut out = BufWriter::new(create_file(name));
+        image::write_ppm(&grid, &good, &region, &mut out).unwrap();
+    }
 }
 
-fn read_claims() -> Vec<Claim> {
-    let re = Regex::new(
+// Why a line of input could not be turned into a claim.
+#[derive(Debug)]
+enum ClaimError {
+    BadShape { line: usize, text: String },
+    BadNumber { line: usize, text: String, field: &'static str, value: String },
+    TooLarge { line: usize, text: String },
+}
+impl Fail for ClaimError {}
+impl std::fmt::Display for ClaimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ClaimError::BadShape { line, ref text } =>
+                write!(f, "line {}: expected '#ID @ X,Y: WxH', got '{}'", line, text),
+            ClaimError::BadNumber { line, ref text, field, ref value } =>
+                write!(f, "line {}: {} '{}' is not a valid number in '{}'", line, field, value, text),
+            ClaimError::TooLarge { line, ref text } =>
+                write!(f, "line {}: claim extends too far in '{}'", line, text),
+        }
+    }
+}
+
+fn claim_regex() -> Regex {
+    Regex::new(
         r#"(?x)
+          ^
+          \s*
           [\#]
           ([0-9]+)      # id
           \s*
@@ -37,30 +235,56 @@ fn read_claims() -> Vec<Claim> {
           \s*
           ([0-9]+)[x]([0-9]+)  # w, h
           \s*
-          "#).unwrap();
+          $
+          "#).unwrap()
+}
 
+// Read all claims, collecting an error for every line that is not a claim;
+// blank lines are skipped.
+fn read_claims<R: BufRead>(reader: R) -> (Vec<Claim>, Vec<ClaimError>) {
+    let re = claim_regex();
     let mut claims = Vec::new();
-    let stdin = io::stdin();
-    for line in stdin.lock().lines() {
+    let mut errors = Vec::new();
+    for (pos, line) in reader.lines().enumerate() {
         let line = line.unwrap();
-        let caps = re.captures(line.as_str()).unwrap();
-        let claim = Claim {
-            id: get_match_int(&caps, 1),
-            x: get_match_int(&caps, 2),
-            y: get_match_int(&caps, 3),
-            w: get_match_int(&caps, 4),
-            h: get_match_int(&caps, 5),
-        };
-        claims.push(claim);
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_claim(&re, pos + 1, &line) {
+            Ok(claim) => claims.push(claim),
+            Err(e) => errors.push(e),
+        }
     }
-    claims
+    (claims, errors)
 }
 
-fn get_match_int(caps: &regex::Captures, pos: usize) -> i32 {
-    caps.get(pos).map_or("", |m| m.as_str()).parse::<i32>().unwrap()
+fn parse_claim(re: &Regex, line: usize, text: &str) -> Result<Claim, ClaimError> {
+    let caps = match re.captures(text) {
+        Some(caps) => caps,
+        None => return Err(ClaimError::BadShape { line, text: text.to_string() }),
+    };
+    let claim = Claim {
+        id: get_match_int(&caps, 1, "id", line, text)?,
+        x: get_match_int(&caps, 2, "x", line, text)?,
+        y: get_match_int(&caps, 3, "y", line, text)?,
+        w: get_match_int(&caps, 4, "width", line, text)?,
+        h: get_match_int(&caps, 5, "height", line, text)?,
+    };
+    if claim.x.checked_add(claim.w).is_none() || claim.y.checked_add(claim.h).is_none() {
+        return Err(ClaimError::TooLarge { line, text: text.to_string() });
+    }
+    Ok(claim)
 }
 
-fn fill_grid(claims: &Vec<Claim>) -> HashMap<(i32, i32), u32> {
+fn get_match_int(caps: &regex::Captures, pos: usize, field: &'static str,
+                 line: usize, text: &str) -> Result<i32, ClaimError> {
+    let value = caps.get(pos).map_or("", |m| m.as_str());
+    value.parse::<i32>().map_err(|_| ClaimError::BadNumber {
+        line, text: text.to_string(), field, value: value.to_string(),
+    })
+}
+
+fn fill_grid(claims: &[Claim]) -> HashMap<(i32, i32), u32> {
     let mut grid = HashMap::new();
     for claim in claims {
         for xpos in claim.x..(claim.x+claim.w) {
@@ -80,8 +304,7 @@ fn fill_grid(claims: &Vec<Claim>) -> HashMap<(i32, i32), u32> {
 
 fn find_bad_squares(grid: &HashMap<(i32, i32), u32>) {
     let mut squares = 0;
-    for (_key, cnt) in grid {
-        // println!("{} {} {}", _key.0, _key.1, cnt);
+    for cnt in grid.values() {
         if *cnt < 2 {
             continue;
         }
@@ -90,12 +313,13 @@ fn find_bad_squares(grid: &HashMap<(i32, i32), u32>) {
     println!("{} bad squares", squares);
 }
 
-fn find_good_claims(claims: &Vec<Claim>, grid: &HashMap<(i32, i32), u32>) {
+fn find_good_claims<'a>(claims: &'a [Claim], grid: &HashMap<(i32, i32), u32>) -> Vec<&'a Claim> {
+    let mut good = Vec::new();
     'claim: for claim in claims {
         for xpos in claim.x..(claim.x+claim.w) {
             for ypos in claim.y..(claim.y+claim.h) {
                 let key = (xpos, ypos);
-                let mut cnt = match grid.get(&key) {
+                let cnt = match grid.get(&key) {
                     Some(&number) => number,
                     _ => 0,
                 };
@@ -105,5 +329,124 @@ fn find_good_claims(claims: &Vec<Claim>, grid: &HashMap<(i32, i32), u32>) {
             }
         }
         println!("{} id OK", claim.id);
+        good.push(claim);
+    }
+    good
+}
+
+// Same results as the grid, using the compressed coverage engine.
+fn sweep_claims(claims: &[Claim]) {
+    let coverage = Coverage::new(claims);
+    for (depth, area) in coverage.histogram() {
+        println!("{} squares covered by {} claims", area, depth);
+    }
+    println!("{} bad squares", coverage.overlapped());
+    for claim in claims {
+        if coverage.is_intact(claim) {
+            println!("{} id OK", claim.id);
+        }
+    }
+}
+
+// Conflict graph between claims, printed and / or exported.
+fn report_conflicts(claims: &[Claim], print: bool, dot: &Option<String>, json: &Option<String>) {
+    let graph = Graph::new(claims);
+    if print {
+        graph.print(claims);
+    }
+    if let Some(ref name) = *dot {
+        let mut out = BufWriter::new(create_file(name));
+        graph.write_dot(claims, &mut out).unwrap();
+    }
+    if let Some(ref name) = *json {
+        let mut out = BufWriter::new(create_file(name));
+        graph.write_json(claims, &mut out).unwrap();
+    }
+}
+
+fn open_file(name: &str) -> File {
+    match File::open(name) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn create_file(name: &str) -> File {
+    match File::create(name) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Answer queries of the form "point X,Y" (claims covering that inch) and
+// "rect X,Y: WxH" (claims intersecting that rectangle), one per line.
+fn run_queries<R: BufRead>(claims: &[Claim], index: &Index, reader: R) {
+    let re = Regex::new(
+        r#"(?x)
+          ^
+          \s*
+          (?:
+            point \s+ (?P<px>[0-9]+)[,](?P<py>[0-9]+)
+          |
+            rect \s+ (?P<rx>[0-9]+)[,](?P<ry>[0-9]+) \s* [:]? \s* (?P<rw>[0-9]+)[x](?P<rh>[0-9]+)
+          )
+          \s*
+          $
+          "#).unwrap();
+
+    for line in reader.lines() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let caps = match re.captures(&line) {
+            Some(caps) => caps,
+            None => {
+                println!("{}: bad query, expected 'point X,Y' or 'rect X,Y: WxH'", line.trim());
+                continue;
+            }
+        };
+        let found = match search(index, &caps) {
+            Ok(found) => found,
+            Err(e) => {
+                println!("{}: bad query, {}", line.trim(), e);
+                continue;
+            }
+        };
+        let ids: Vec<String> = found.iter().map(|&pos| format!("#{}", claims[pos].id)).collect();
+        println!("{}: {}", line.trim(), if ids.is_empty() { "none".to_string() } else { ids.join(" ") });
+    }
+}
+
+fn search(index: &Index, caps: &regex::Captures) -> Result<Vec<usize>, String> {
+    if caps.name("px").is_some() {
+        let (x, y) = (get_capture_as_i64(caps, "px")?, get_capture_as_i64(caps, "py")?);
+        return index.search_point(x, y).ok_or_else(|| "point is out of range".to_string());
+    }
+    let (x, y) = (get_capture_as_i64(caps, "rx")?, get_capture_as_i64(caps, "ry")?);
+    let (w, h) = (get_capture_as_i64(caps, "rw")?, get_capture_as_i64(caps, "rh")?);
+    let rect = Rect::new(x, y, w, h).ok_or_else(|| "rectangle is out of range".to_string())?;
+    Ok(index.search(&rect))
+}
+
+fn get_capture_as_i64(caps: &regex::Captures, name: &str) -> Result<i64, String> {
+    let text = caps.name(name).map_or("", |m| m.as_str());
+    text.parse::<i64>().map_err(|_| format!("{} is out of range", text))
+}
+
+// Claims that go past the edge of the sheet, and the largest free spot on it.
+fn check_sheet(claims: &[Claim], grid: &HashMap<(i32, i32), u32>, sheet: &Sheet) {
+    for claim in sheet.out_of_bounds(claims) {
+        println!("{} id extends past the {}x{} sheet", claim.id, sheet.w, sheet.h);
+    }
+    match sheet.largest_free(grid) {
+        Some((x, y, w, h)) => println!("largest free rectangle {},{}: {}x{} ({} squares)", x, y, w, h, w as i64 * h as i64),
+        None => println!("no free fabric on the sheet"),
     }
 }
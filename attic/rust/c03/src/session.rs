@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::BufRead;
+
+use super::{Claim, claim_regex, parse_claim};
+use graph::shared_area;
+
+// Claims being edited one at a time.  The coverage counts start out as the
+// grid from fill_grid and are then updated only over the edited claim, and
+// only the claims touching it get their intact status checked again.
+pub struct Session {
+    claims: BTreeMap<i32, Claim>,
+    grid: HashMap<(i32, i32), u32>,
+    overlapped: usize,
+    intact: BTreeSet<i32>,
+}
+impl Session {
+    // Claim ids must be unique, or edits could not tell the claims apart.
+    pub fn new(claims: Vec<Claim>, grid: HashMap<(i32, i32), u32>) -> Result<Session, String> {
+        let overlapped = grid.values().filter(|&&cnt| cnt > 1).count();
+        let mut session = Session { claims: BTreeMap::new(), grid, overlapped, intact: BTreeSet::new() };
+        for claim in claims {
+            if session.claims.contains_key(&claim.id) {
+                return Err(format!("claim #{} appears more than once", claim.id));
+            }
+            if session.is_intact(&claim) {
+                session.intact.insert(claim.id);
+            }
+            session.claims.insert(claim.id, claim);
+        }
+        Ok(session)
+    }
+
+    pub fn print(&self) {
+        let ids: Vec<String> = self.intact.iter().map(|id| format!("#{}", id)).collect();
+        println!("{} bad squares, intact: {}", self.overlapped, if ids.is_empty() { "none".to_string() } else { ids.join(" ") });
+    }
+
+    pub fn add(&mut self, claim: Claim) -> Result<(), String> {
+        if self.claims.contains_key(&claim.id) {
+            return Err(format!("claim #{} already exists", claim.id));
+        }
+        self.cover(&claim, true);
+        self.claims.insert(claim.id, claim.clone());
+        self.recheck(&claim);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: i32) -> Result<(), String> {
+        let claim = match self.claims.remove(&id) {
+            Some(claim) => claim,
+            None => return Err(format!("no claim #{}", id)),
+        };
+        self.cover(&claim, false);
+        self.intact.remove(&id);
+        self.recheck(&claim);
+        Ok(())
+    }
+
+    pub fn resize(&mut self, id: i32, w: i32, h: i32) -> Result<(), String> {
+        let mut claim = match self.claims.get(&id) {
+            Some(claim) => claim.clone(),
+            None => return Err(format!("no claim #{}", id)),
+        };
+        if w <= 0 || h <= 0 {
+            return Err(format!("{}x{}: size must be positive", w, h));
+        }
+        if claim.x.checked_add(w).is_none() || claim.y.checked_add(h).is_none() {
+            return Err(format!("claim #{} would extend too far", id));
+        }
+        self.remove(id)?;
+        claim.w = w;
+        claim.h = h;
+        self.add(claim)
+    }
+
+    // Add or take away one claim from the coverage counts.
+    fn cover(&mut self, claim: &Claim, add: bool) {
+        for xpos in claim.x..(claim.x+claim.w) {
+            for ypos in claim.y..(claim.y+claim.h) {
+                let key = (xpos, ypos);
+                let cnt = self.grid.get(&key).cloned().unwrap_or(0);
+                let cnt = if add { cnt + 1 } else { cnt - 1 };
+                if add && cnt == 2 {
+                    self.overlapped += 1;
+                }
+                if !add && cnt == 1 {
+                    self.overlapped -= 1;
+                }
+                if cnt == 0 {
+                    self.grid.remove(&key);
+                } else {
+                    self.grid.insert(key, cnt);
+                }
+            }
+        }
+    }
+
+    fn is_intact(&self, claim: &Claim) -> bool {
+        for xpos in claim.x..(claim.x+claim.w) {
+            for ypos in claim.y..(claim.y+claim.h) {
+                if self.grid.get(&(xpos, ypos)).cloned().unwrap_or(0) > 1 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // Only claims sharing fabric with the edited one can change status.
+    fn recheck(&mut self, edited: &Claim) {
+        let touched: Vec<i32> = self.claims.values()
+            .filter(|c| c.id == edited.id || shared_area(c, edited) > 0)
+            .map(|c| c.id)
+            .collect();
+        for id in touched {
+            if self.is_intact(&self.claims[&id]) {
+                self.intact.insert(id);
+            } else {
+                self.intact.remove(&id);
+            }
+        }
+    }
+
+    // Run commands, one per line, printing the state after every edit:
+    //   add #ID @ X,Y: WxH
+    //   remove ID
+    //   resize ID WxH
+    pub fn run<R: BufRead>(&mut self, reader: R) {
+        let re = claim_regex();
+        self.print();
+        for (pos, line) in reader.lines().enumerate() {
+            let line = line.unwrap();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let result = match (words[0], words.len()) {
+                ("add", _) => {
+                    let text = line.trim_start()["add".len()..].to_string();
+                    match parse_claim(&re, pos + 1, &text) {
+                        Ok(claim) => self.add(claim),
+                        Err(e) => Err(e.to_string()),
+                    }
+                }
+                ("remove", 2) => match parse_id(words[1]) {
+                    Some(id) => self.remove(id),
+                    None => Err(format!("bad claim id '{}'", words[1])),
+                },
+                ("resize", 3) => match (parse_id(words[1]), parse_size(words[2])) {
+                    (Some(id), Some((w, h))) => self.resize(id, w, h),
+                    _ => Err("expected 'resize ID WxH'".to_string()),
+                },
+                _ => Err("expected 'add #ID @ X,Y: WxH', 'remove ID' or 'resize ID WxH'".to_string()),
+            };
+            match result {
+                Ok(()) => {
+                    print!("{}: ", line.trim());
+                    self.print();
+                }
+                Err(e) => println!("{}: {}", line.trim(), e),
+            }
+        }
+    }
+}
+
+fn parse_id(text: &str) -> Option<i32> {
+    text.trim_start_matches('#').parse().ok()
+}
+
+fn parse_size(text: &str) -> Option<(i32, i32)> {
+    let (w, h) = text.split_once('x')?;
+    Some((w.parse().ok()?, h.parse().ok()?))
+}
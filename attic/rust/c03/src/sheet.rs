@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+use super::Claim;
+
+// Size of the sheet of fabric, by default 1000 x 1000 inches.
+pub struct Sheet {
+    pub w: i32,
+    pub h: i32,
+}
+impl Sheet {
+    pub fn new(w: i32, h: i32) -> Sheet {
+        Sheet { w, h }
+    }
+
+    // Parse a size given as "WxH"; both must be positive.
+    pub fn parse(text: &str) -> Option<Sheet> {
+        let (w, h) = text.split_once('x')?;
+        let sheet = Sheet::new(w.trim().parse().ok()?, h.trim().parse().ok()?);
+        if sheet.w <= 0 || sheet.h <= 0 {
+            return None;
+        }
+        Some(sheet)
+    }
+
+    // Claims that do not fit inside the sheet.
+    pub fn out_of_bounds<'a>(&self, claims: &'a [Claim]) -> Vec<&'a Claim> {
+        claims.iter()
+            .filter(|c| c.x < 0 || c.y < 0 || c.x + c.w > self.w || c.y + c.h > self.h)
+            .collect()
+    }
+
+    // Largest rectangle (x, y, w, h) inside the sheet that no claim touches,
+    // using the coverage from fill_grid.  Scan row by row, keeping for every
+    // column the height of free fabric ending at that row, and find the
+    // largest rectangle under that histogram with a stack.
+    pub fn largest_free(&self, grid: &HashMap<(i32, i32), u32>) -> Option<(i32, i32, i32, i32)> {
+        let mut heights = vec![0i32; self.w as usize];
+        let mut best: Option<(i32, i32, i32, i32)> = None;
+        let mut best_area: i64 = 0;
+        for y in 0..self.h {
+            for x in 0..self.w {
+                let used = grid.contains_key(&(x, y));
+                heights[x as usize] = if used { 0 } else { heights[x as usize] + 1 };
+            }
+
+            // stack of (start column, height) with increasing heights
+            let mut stack: Vec<(i32, i32)> = Vec::new();
+            for x in 0..=self.w {
+                let height = if x < self.w { heights[x as usize] } else { 0 };
+                let mut start = x;
+                while let Some(&(left, top)) = stack.last() {
+                    if top < height {
+                        break;
+                    }
+                    stack.pop();
+                    let area = top as i64 * (x - left) as i64;
+                    if area > best_area {
+                        best_area = area;
+                        best = Some((left, y - top + 1, x - left, top));
+                    }
+                    start = left;
+                }
+                stack.push((start, height));
+            }
+        }
+        best
+    }
+}
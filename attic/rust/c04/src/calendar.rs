@@ -0,0 +1,278 @@
+
+use std::fmt;
+use std::str::FromStr;
+
+// Why a date or time could not be built.
+#[derive(Debug, PartialEq)]
+pub enum DateError {
+    BadFormat(String),
+    OutOfRange(String),
+}
+impl fmt::Display for DateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DateError::BadFormat(ref text) => write!(f, "'{}' is not a valid date / time", text),
+            DateError::OutOfRange(ref text) => write!(f, "{} does not exist", text),
+        }
+    }
+}
+
+pub fn is_leap_year(y: i32) -> bool {
+    (y % 4 == 0 && y % 100 != 0) || y % 400 == 0
+}
+
+pub fn days_in_month(y: i32, m: i32) -> i32 {
+    match m {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 => if is_leap_year(y) { 29 } else { 28 },
+        _ => 0,
+    }
+}
+
+// Days of the week; only the tests look at them so far.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+impl Weekday {
+    #[allow(dead_code)]
+    const ALL: [Weekday; 7] = [Weekday::Monday, Weekday::Tuesday, Weekday::Wednesday, Weekday::Thursday,
+                               Weekday::Friday, Weekday::Saturday, Weekday::Sunday];
+}
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// A validated day in the proleptic Gregorian calendar.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    y: i32,
+    m: i32,
+    d: i32,
+}
+impl Date {
+    pub fn new(y: i32, m: i32, d: i32) -> Result<Date, DateError> {
+        if !(1..=12).contains(&m) || d < 1 || d > days_in_month(y, m) {
+            return Err(DateError::OutOfRange(format!("{:04}-{:02}-{:02}", y, m, d)));
+        }
+        Ok(Date { y, m, d })
+    }
+
+    // Conversion from a Julian day number (Fliegel & Van Flandern).
+    pub fn from_julian(j: i32) -> Date {
+        let mut l = j + 68569;
+        let n = (4 * l) / 146097;
+        l -= (146097 * n + 3) / 4;
+        let i = (4000 * (l + 1)) / 1461001;
+        l -= (1461 * i) / 4 - 31;
+        let h = (80 * l) / 2447;
+        let k = h / 11;
+        let d = l - (2447 * h) / 80;
+        let m = h + 2 - (12 * k);
+        let y = 100 * (n - 49) + i + k;
+        Date { y, m, d }
+    }
+
+    // Julian day number, counting days since noon on 4713-01-01 BC.
+    pub fn julian(&self) -> i32 {
+        let p = (self.m - 14) / 12;
+        (1461 * (self.y + 4800 + p)) / 4 +
+            (367 * (self.m - 2 - 12 * p)) / 12 -
+            (3 * ((self.y + 4900 + p) / 100)) / 4 +
+            (self.d - 32075)
+    }
+
+    #[allow(dead_code)]
+    pub fn year(&self) -> i32 {
+        self.y
+    }
+    pub fn month(&self) -> i32 {
+        self.m
+    }
+    pub fn day(&self) -> i32 {
+        self.d
+    }
+
+    #[allow(dead_code)]
+    pub fn weekday(&self) -> Weekday {
+        Weekday::ALL[self.julian().rem_euclid(7) as usize]
+    }
+
+    pub fn add_days(&self, days: i32) -> Date {
+        Date::from_julian(self.julian() + days)
+    }
+
+    #[allow(dead_code)]
+    pub fn days_until(&self, other: &Date) -> i32 {
+        other.julian() - self.julian()
+    }
+
+    pub fn at(&self, h: i32, m: i32) -> Result<DateTime, DateError> {
+        DateTime::new(*self, h, m)
+    }
+}
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.y, self.m, self.d)
+    }
+}
+impl FromStr for Date {
+    type Err = DateError;
+
+    // Parse a date given as YYYY-MM-DD.
+    fn from_str(text: &str) -> Result<Date, DateError> {
+        let parts: Vec<&str> = text.trim().split('-').collect();
+        if parts.len() != 3 {
+            return Err(DateError::BadFormat(text.to_string()));
+        }
+        let mut numbers = [0; 3];
+        for (pos, part) in parts.iter().enumerate() {
+            numbers[pos] = match part.parse::<i32>() {
+                Ok(n) => n,
+                Err(_) => return Err(DateError::BadFormat(text.to_string())),
+            };
+        }
+        Date::new(numbers[0], numbers[1], numbers[2])
+    }
+}
+
+// A validated minute within a day.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    date: Date,
+    h: i32,
+    m: i32,
+}
+impl DateTime {
+    pub fn new(date: Date, h: i32, m: i32) -> Result<DateTime, DateError> {
+        if !(0..24).contains(&h) || !(0..60).contains(&m) {
+            return Err(DateError::OutOfRange(format!("{} {:02}:{:02}", date, h, m)));
+        }
+        Ok(DateTime { date, h, m })
+    }
+
+    pub fn from_stamp(stamp: i64) -> DateTime {
+        let day = stamp.div_euclid(24 * 60);
+        let minute = stamp.rem_euclid(24 * 60) as i32;
+        DateTime { date: Date::from_julian(day as i32), h: minute / 60, m: minute % 60 }
+    }
+
+    // Minutes since midnight at the start of Julian day zero.
+    pub fn stamp(&self) -> i64 {
+        (self.date.julian() as i64 * 24 + self.h as i64) * 60 + self.m as i64
+    }
+
+    pub fn date(&self) -> Date {
+        self.date
+    }
+    #[allow(dead_code)]
+    pub fn hour(&self) -> i32 {
+        self.h
+    }
+    #[allow(dead_code)]
+    pub fn minute(&self) -> i32 {
+        self.m
+    }
+
+    #[allow(dead_code)]
+    pub fn add_minutes(&self, minutes: i64) -> DateTime {
+        DateTime::from_stamp(self.stamp() + minutes)
+    }
+}
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:02}:{:02}", self.date, self.h, self.m)
+    }
+}
+impl FromStr for DateTime {
+    type Err = DateError;
+
+    // Parse a date and time given as YYYY-MM-DD HH:MM.
+    fn from_str(text: &str) -> Result<DateTime, DateError> {
+        let bad = || DateError::BadFormat(text.to_string());
+        let (date, time) = text.trim().split_once(' ').ok_or_else(bad)?;
+        let (h, m) = time.trim().split_once(':').ok_or_else(bad)?;
+        let h = h.parse::<i32>().map_err(|_| bad())?;
+        let m = m.parse::<i32>().map_err(|_| bad())?;
+        DateTime::new(date.parse::<Date>()?, h, m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchor() {
+        // well known: 2000-01-01 was a Saturday, with Julian day 2451545
+        let anchor = Date::new(2000, 1, 1).unwrap();
+        assert_eq!(anchor.julian(), 2451545);
+        assert_eq!(anchor.weekday(), Weekday::Saturday);
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for &(y, m, d) in &[(1518, 2, 29), (1518, 2, 30), (1900, 2, 29), (1518, 4, 31), (1518, 13, 1), (1518, 0, 10)] {
+            assert!(Date::new(y, m, d).is_err(), "{:04}-{:02}-{:02} was accepted", y, m, d);
+        }
+        assert!(Date::new(2000, 2, 29).is_ok());
+        assert!(Date::new(1518, 11, 1).unwrap().at(24, 0).is_err());
+        assert!(Date::new(1518, 11, 1).unwrap().at(23, 60).is_err());
+        assert!("1518-11-01".parse::<Date>().is_ok());
+        assert!("1518-11".parse::<Date>().is_err());
+        assert!("1518-11-01 0:x".parse::<DateTime>().is_err());
+    }
+
+    // Walk day by day over a few centuries around the puzzle dates, counting
+    // days by hand, and check that the Julian day formulas and everything
+    // built on them agree.
+    #[test]
+    fn round_trip() {
+        let to = Date::new(2100, 12, 31).unwrap();
+        let mut prev: Option<Date> = None;
+        let (mut y, mut m, mut d) = (1500, 1, 1);
+        loop {
+            let date = Date::new(y, m, d).unwrap();
+            if date > to {
+                break;
+            }
+            let j = date.julian();
+            assert_eq!(Date::from_julian(j), date);
+            if let Some(prev) = prev {
+                assert_eq!(prev.julian() + 1, j);
+                assert_eq!(prev.add_days(1), date);
+                assert_eq!(prev.days_until(&date), 1);
+                assert_eq!(date.weekday(), Weekday::ALL[(prev.weekday() as usize + 1) % 7]);
+            }
+            assert_eq!(date.to_string().parse::<Date>(), Ok(date));
+            assert_eq!((date.year(), date.month(), date.day()), (y, m, d));
+
+            let time = date.at(23, 59).unwrap();
+            assert_eq!(DateTime::from_stamp(time.stamp()), time);
+            assert_eq!(time.add_minutes(1), date.add_days(1).at(0, 0).unwrap());
+            assert_eq!(time.to_string().parse::<DateTime>(), Ok(time));
+            assert_eq!((time.date(), time.hour(), time.minute()), (date, 23, 59));
+
+            prev = Some(date);
+            d += 1;
+            if d > days_in_month(y, m) {
+                d = 1;
+                m += 1;
+            }
+            if m > 12 {
+                m = 1;
+                y += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::rc::Rc;
+use regex::{self, Regex};
+use calendar::{Date, DateTime};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Begins(i32),
+    Sleeps,
+    Wakes,
+}
+
+// Where a record came from; lines are one-based.
+#[derive(Clone, Debug)]
+pub struct Place {
+    pub file: Rc<str>,
+    pub line: usize,
+}
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+// One record from the log.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub place: Place,
+    pub time: DateTime,
+    pub kind: Kind,
+}
+
+// Parse every line of one log file. Timestamps may use a 'T' instead of the
+// space and may carry seconds, which are dropped: the puzzle works in minutes.
+pub fn parse_lines(file: &str, lines: &[String]) -> Result<Vec<Event>, String> {
+    let re = Regex::new(
+        r#"(?x)
+          ^
+          \s*
+          [\[]
+          (?P<dy>\d{4})
+          [-]
+          (?P<dm>\d{2})
+          [-]
+          (?P<dd>\d{2})
+          (?:\s+|T)
+          (?P<th>\d{2})
+          [:]
+          (?P<tm>\d{2})
+          (?:[:](?P<ts>\d{2})(?:[.]\d+)?)?
+          [\]]
+          \s+
+          (Guard\ \#(?P<guard>[0-9]+)|(?P<sleeps>falls)|(?P<wakes>wakes))
+          .*
+          $
+          "#).unwrap();
+
+    let file: Rc<str> = Rc::from(file);
+    let mut events = Vec::new();
+    for (pos, line) in lines.iter().enumerate() {
+        let place = Place { file: file.clone(), line: pos + 1 };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let caps = match re.captures(line) {
+            Some(caps) => caps,
+            None => return Err(format!("{}: cannot parse '{}'", place, line)),
+        };
+
+        let get = |name: &str| get_capture_as_i32(&caps, name.to_string())
+            .map_err(|value| format!("{}: {} is out of range in '{}'", place, value, line));
+        let dy = get("dy")?;
+        let dm = get("dm")?;
+        let dd = get("dd")?;
+        let th = get("th")?;
+        let tm = get("tm")?;
+        if get("ts")? > 59 {
+            return Err(format!("{}: bad seconds in '{}'", place, line));
+        }
+        let time = match Date::new(dy, dm, dd).and_then(|date| date.at(th, tm)) {
+            Ok(time) => time,
+            Err(e) => return Err(format!("{}: {}", place, e)),
+        };
+
+        let guard = get_capture_as_i32(&caps, "guard".to_string())
+            .map_err(|id| format!("{}: bad guard id #{} in '{}'", place, id, line))?;
+        let kind = if guard >= 0 {
+            Kind::Begins(guard)
+        } else if get_capture_as_bool(&caps, "sleeps".to_string()) {
+            Kind::Sleeps
+        } else {
+            Kind::Wakes
+        };
+        events.push(Event { place, time, kind });
+    }
+    Ok(events)
+}
+
+// Put the events of all files in chronological order; events with the same
+// time keep the order of the files and lines they came from. The same event
+// found in several files, as happens when their periods overlap, is only kept
+// once; repeats within one file are left for the validator to report.
+// Returns the merged events and how many were dropped.
+pub fn merge(files: Vec<Vec<Event>>) -> (Vec<Event>, usize) {
+    let mut all: Vec<Event> = files.into_iter().flatten().collect();
+    all.sort_by_key(|event| event.time);
+
+    let mut events: Vec<Event> = Vec::with_capacity(all.len());
+    let mut same_time = 0;
+    let mut dropped = 0;
+    for event in all {
+        if events.last().is_none_or(|last| last.time != event.time) {
+            same_time = events.len();
+        }
+        let repeated = events[same_time..].iter()
+            .any(|seen| seen.kind == event.kind && !Rc::ptr_eq(&seen.place.file, &event.place.file));
+        if repeated {
+            dropped += 1;
+        } else {
+            events.push(event);
+        }
+    }
+    (events, dropped)
+}
+
+// -1 if the capture is missing; the text if it does not fit.
+fn get_capture_as_i32(caps: &regex::Captures, name: String) -> Result<i32, String> {
+    match caps.name(&name) {
+        Some(cap) => cap.as_str().parse::<i32>().map_err(|_| cap.as_str().to_string()),
+        None => Ok(-1),
+    }
+}
+
+fn get_capture_as_bool(caps: &regex::Captures, name: String) -> bool {
+    caps.name(&name).is_some()
+}
@@ -1,139 +1,398 @@
 extern crate regex;
 
-use std::io::{self, BufRead};
+mod calendar;
+mod log;
+mod model;
+mod query;
+mod report;
+mod validate;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter};
 use std::collections::HashMap;
-use regex::Regex;
+use calendar::DateTime;
+use log::{Event, Kind};
 
 fn main() {
-    let lines = read_lines();
-    let shifts = process_lines(&lines);
-    shifts.analize();
+    let mut window = Window::new(0, 60);
+    let mut validate_only = false;
+    let mut strict = false;
+    let mut report: Option<String> = None;
+    let mut heatmap = false;
+    let mut inputs: Vec<String> = Vec::new();
+    let mut query: Option<String> = None;
+    let mut chances: Vec<String> = Vec::new();
+    let mut safest: Option<usize> = None;
+    let mut windowed = false;
+    for arg in env::args().skip(1) {
+        if arg == "--validate" {
+            validate_only = true;
+        } else if arg == "--strict" {
+            strict = true;
+        } else if arg == "--heatmap" {
+            heatmap = true;
+        // logs may be given as --log=FILE or just FILE, as many as needed
+        } else if let Some(name) = arg.strip_prefix("--log=") {
+            inputs.push(name.to_string());
+        } else if !arg.starts_with("--") {
+            inputs.push(arg.clone());
+        // queries come from stdin, unless a file is given
+        } else if arg == "--query" {
+            query = Some("-".to_string());
+        } else if let Some(name) = arg.strip_prefix("--query=") {
+            query = Some(name.to_string());
+        } else if let Some(text) = arg.strip_prefix("--chance=") {
+            chances.push(text.to_string());
+        } else if arg == "--safest" {
+            safest = Some(10);
+        } else if let Some(text) = arg.strip_prefix("--safest=") {
+            safest = match text.parse::<usize>() {
+                Ok(count) => Some(count),
+                Err(_) => {
+                    eprintln!("{}: bad count", text);
+                    std::process::exit(1);
+                }
+            };
+        } else if let Some(prefix) = arg.strip_prefix("--report=") {
+            report = Some(prefix.to_string());
+        } else if let Some(text) = arg.strip_prefix("--window=") {
+            window = match Window::parse(text) {
+                Some(window) => window,
+                None => {
+                    eprintln!("{}: bad window, expected HH:MM-HH:MM", text);
+                    std::process::exit(1);
+                }
+            };
+            windowed = true;
+        } else {
+            eprintln!("{}: unknown argument", arg);
+            std::process::exit(1);
+        }
+    }
+
+    // only one mode runs; the window does not matter to the first two
+    let modes: Vec<&str> = [
+        ("--validate", validate_only),
+        ("--query", query.is_some()),
+        ("--chance or --safest", !chances.is_empty() || safest.is_some()),
+        ("--report or --heatmap", report.is_some() || heatmap),
+    ].iter().filter(|mode| mode.1).map(|mode| mode.0).collect();
+    if modes.len() > 1 {
+        eprintln!("{} cannot be combined with {}", modes[0], modes[1]);
+        std::process::exit(1);
+    }
+    if windowed && (validate_only || query.is_some()) {
+        eprintln!("--window cannot be combined with {}", modes[0]);
+        std::process::exit(1);
+    }
+
+    if inputs.is_empty() && query == Some("-".to_string()) {
+        eprintln!("--query reads stdin, so the log must come from --log=FILE");
+        std::process::exit(1);
+    }
+
+    let events = read_events(&inputs);
+
+    // problems are only warnings, unless we are strict about them
+    let problems = validate::check(&events);
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    if validate_only {
+        println!("{} events, {} problems", events.len(), problems.len());
+    }
+    if !problems.is_empty() && (strict || validate_only) {
+        std::process::exit(1);
+    }
+    if validate_only {
+        return;
+    }
+
+    let shifts = process_events(&events, &window);
+    if let Some(ref name) = query {
+        if name == "-" {
+            let stdin = io::stdin();
+            query::run(&shifts, stdin.lock());
+        } else {
+            query::run(&shifts, BufReader::new(open_file(name)));
+        }
+        return;
+    }
+    if !chances.is_empty() || safest.is_some() {
+        let model = model::Model::new(&shifts, &window);
+        for text in &chances {
+            if let Err(e) = model.print_chance(text) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        if let Some(count) = safest {
+            model.print_safest(count);
+        }
+        return;
+    }
+    if report.is_none() && !heatmap {
+        shifts.analize(&window);
+        return;
+    }
+
+    let r = report::Report::new(&shifts, &window);
+    if let Some(prefix) = report {
+        let written = r.write_heatmap_csv(&mut create_file(&format!("{}.heatmap.csv", prefix)))
+            .and_then(|_| r.write_guards_csv(&mut create_file(&format!("{}.guards.csv", prefix))))
+            .and_then(|_| r.write_winners_csv(&mut create_file(&format!("{}.winners.csv", prefix))))
+            .and_then(|_| r.write_json(&mut create_file(&format!("{}.json", prefix))));
+        if let Err(e) = written {
+            eprintln!("{}: {}", prefix, e);
+            std::process::exit(1);
+        }
+    }
+    if heatmap {
+        r.print_heatmap();
+    }
+}
+
+fn open_file(name: &str) -> File {
+    match File::open(name) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            std::process::exit(1);
+        }
+    }
 }
 
-fn read_lines() -> Vec<String> {
+fn create_file(name: &str) -> BufWriter<File> {
+    match File::create(name) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => {
+            eprintln!("{}: {}", name, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Read and merge the given log files, or stdin if there are none.
+fn read_events(inputs: &[String]) -> Vec<Event> {
+    let mut files = Vec::new();
+    if inputs.is_empty() {
+        let stdin = io::stdin();
+        files.push(log::parse_lines("<stdin>", &read_lines(stdin.lock())));
+    }
+    for name in inputs {
+        files.push(log::parse_lines(name, &read_lines(BufReader::new(open_file(name)))));
+    }
+    let files = match files.into_iter().collect::<Result<Vec<_>, _>>() {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let (events, dropped) = log::merge(files);
+    if dropped > 0 {
+        eprintln!("{} events found in more than one file were merged", dropped);
+    }
+    events
+}
+
+fn read_lines<R: BufRead>(reader: R) -> Vec<String> {
     let mut lines = Vec::new();
-    let stdin = io::stdin();
-    for line in stdin.lock().lines() {
+    for line in reader.lines() {
         lines.push(line.unwrap());
     }
-    lines.sort();
     lines
 }
 
-fn process_lines(lines: &Vec<String>) -> Shifts {
-    let re = Regex::new(
-        r#"(?x)
-          ^
-          \s*
-          [\[]
-          (?P<dy>\d{4})
-          [-]
-          (?P<dm>\d{2})
-          [-]
-          (?P<dd>\d{2})
-          \s+
-          (?P<th>\d{2})
-          [:]
-          (?P<tm>\d{2})
-          [\]]
-          \s+
-          (Guard\ \#(?P<guard>[0-9]+)|(?P<sleeps>falls)|(?P<wakes>wakes))
-          .*
-          $
-          "#).unwrap();
-
+fn process_events(events: &[Event], window: &Window) -> Shifts {
     let mut shifts = Shifts::new();
-    for line in lines {
-        let caps = re.captures(&line).unwrap();
-
-        let dy = get_capture_as_i32(&caps, "dy".to_string());
-        let dm = get_capture_as_i32(&caps, "dm".to_string());
-        let dd = get_capture_as_i32(&caps, "dd".to_string());
-        let mut date = Date::new_from_ymd(dy, dm, dd);
-
-        let th = get_capture_as_i32(&caps, "th".to_string());
-        let tm = get_capture_as_i32(&caps, "tm".to_string());
-
-        let guard = get_capture_as_i32(&caps, "guard".to_string());
-        if guard >= 0 {
-            let mut day = date.get_julian();
-            if th > 0 {
-                // hour > 0 => it actually refers to PREVIOUS day (ex: 23:57)
-                day += 1;
+    for event in events {
+        let stamp = event.time.stamp();
+
+        if let Kind::Begins(guard) = event.kind {
+            if let Some(shift) = shifts.list.last_mut() {
+                shift.finish(window, Some(stamp));
             }
-            shifts.list.push(Shift::new(day, guard));
+            shifts.list.push(Shift::new(event.time, guard));
+            continue;
         }
 
-        let sleeps = get_capture_as_bool(&caps, "sleeps".to_string());
-        let wakes = get_capture_as_bool(&caps, "wakes".to_string());
-        if sleeps && wakes {
-            panic!("Guard cannot sleep and wake at the same time");
-        }
-        if sleeps || wakes {
-            let pos = shifts.list.len()-1;
-            let ref mut shift = shifts.list[pos];
-            let mut s0 = date.get_stamp(th, tm);
-            if s0 < shift.s0 {
-                s0 = shift.s0;
-            }
-            for s in s0..shift.s1 {
-                let k = (s - shift.s0) as usize;
-                shift.asleep[k] = sleeps;
-            }
+        // events before the first shift were reported by the validator
+        let shift = match shifts.list.last_mut() {
+            Some(shift) => shift,
+            None => continue,
+        };
+        if event.kind == Kind::Sleeps {
+            shift.sleep(stamp);
+        } else {
+            shift.wake(stamp);
         }
     }
+    if let Some(shift) = shifts.list.last_mut() {
+        shift.finish(window, None);
+    }
     shifts
 }
 
-fn get_capture_as_i32(caps: &regex::Captures, name: String) -> i32 {
-    match caps.name(&name) {
-        Some(cap) => cap.as_str().parse::<i32>().unwrap(),
-        None => -1,
-    }
+// Part of every day we look at, such as the midnight hour; it may wrap
+// around midnight, and an empty window means the whole day.
+struct Window {
+    start: i64,
+    len: i64,
 }
+impl Window {
+    const DAY: i64 = 24 * 60;
 
-fn get_capture_as_bool(caps: &regex::Captures, name: String) -> bool {
-    match caps.name(&name) {
-        Some(_) => true,
-        None => false,
+    fn new(start: i64, len: i64) -> Window {
+        Window { start, len }
+    }
+
+    // Parse a window given as "HH:MM-HH:MM", end not included.
+    fn parse(text: &str) -> Option<Window> {
+        let minute = |t: &str| -> Option<i64> {
+            let (h, m) = t.trim().split_once(':')?;
+            let (h, m) = (h.parse::<i64>().ok()?, m.parse::<i64>().ok()?);
+            if !(0..24).contains(&h) || !(0..60).contains(&m) {
+                return None;
+            }
+            Some(h * 60 + m)
+        };
+        let (from, to) = text.split_once('-')?;
+        let (from, to) = (minute(from)?, minute(to)?);
+        let len = (to - from).rem_euclid(Window::DAY);
+        Some(Window::new(from, if len == 0 { Window::DAY } else { len }))
+    }
+
+    // Position of a minute stamp inside the window, if it falls in it.
+    fn offset(&self, stamp: i64) -> Option<usize> {
+        let offset = (stamp - self.start).rem_euclid(Window::DAY);
+        if offset < self.len { Some(offset as usize) } else { None }
+    }
+
+    // Clock time for a position inside the window.
+    fn label(&self, pos: i32) -> String {
+        let minute = (self.start + pos as i64).rem_euclid(Window::DAY);
+        format!("{:02}:{:02}", minute / 60, minute % 60)
+    }
+
+    // Stamp where the first occurrence of the window that ends after the
+    // given one ends.
+    fn end_after(&self, stamp: i64) -> i64 {
+        let mut day = stamp.div_euclid(Window::DAY) - 1;
+        while day * Window::DAY + self.start + self.len <= stamp {
+            day += 1;
+        }
+        day * Window::DAY + self.start + self.len
+    }
+
+    // Stamps where the window starts, for every day it overlaps [s0, s1).
+    fn occurrences(&self, s0: i64, s1: i64) -> Vec<i64> {
+        let mut starts = Vec::new();
+        let mut day = s0.div_euclid(Window::DAY) - 1;
+        while day * Window::DAY + self.start < s1 {
+            let start = day * Window::DAY + self.start;
+            if start + self.len > s0 {
+                starts.push(start);
+            }
+            day += 1;
+        }
+        starts
     }
 }
 
+// One guard on duty, from the moment they begin until their last event or
+// the end of the window they came for, with every nap taken as an interval
+// of minute stamps [from, to).
 struct Shift {
-    day: i32,
     guard: i32,
-    asleep: [bool; 60],
-    s0: i32,
-    s1: i32,
+    start: i64,
+    end: i64,
+    naps: Vec<(i64, i64)>,
+    asleep_since: Option<i64>,
 }
 impl Shift {
-    fn new(day: i32, guard: i32) -> Shift {
-        let mut date = Date::new_from_julian(day);
-        Shift { day, guard, asleep: [false; 60],
-                s0: date.get_stamp(0, 0), s1: date.get_stamp(1, 0) }
+    fn new(start: DateTime, guard: i32) -> Shift {
+        let start = start.stamp();
+        Shift { guard, start, end: start, naps: Vec::new(), asleep_since: None }
     }
 
-    fn print(&self) {
-        let mut date = Date::new_from_julian(self.day);
-        let (_, m, d) = date.get_ymd();
-        print!("{:02}-{:02} #{:5} ", m, d, self.guard);
-        for s in 0..60 {
-            print!("{}", if self.asleep[s] { "#" } else { "." });
+    fn sleep(&mut self, stamp: i64) {
+        self.end = self.end.max(stamp);
+        if self.asleep_since.is_none() {
+            self.asleep_since = Some(stamp);
+        }
+    }
+
+    fn wake(&mut self, stamp: i64) {
+        self.end = self.end.max(stamp);
+        if let Some(since) = self.asleep_since.take() {
+            self.naps.push((since, stamp));
+        }
+    }
+
+    // The shift never runs past the arrival of the next guard, if any; a
+    // guard still asleep then sleeps until relieved.
+    fn finish(&mut self, window: &Window, next: Option<i64>) {
+        let end = self.end.max(window.end_after(self.start));
+        self.end = next.map_or(end, |next| end.min(next));
+        if let Some(since) = self.asleep_since.take() {
+            let until = next.unwrap_or(self.end);
+            self.naps.push((since, until));
+            self.end = self.end.max(until);
         }
-        println!("");
+    }
+
+    fn is_asleep(&self, stamp: i64) -> bool {
+        self.naps.iter().any(|&(from, to)| from <= stamp && stamp < to)
+    }
+
+    // Every minute of the window occurrence beginning at start: None while
+    // the guard was not on duty, otherwise whether they were asleep.
+    fn minutes(&self, window: &Window, start: i64) -> Vec<Option<bool>> {
+        (start..start + window.len)
+            .map(|stamp| if stamp < self.start || stamp >= self.end {
+                None
+            } else {
+                Some(self.is_asleep(stamp))
+            })
+            .collect()
+    }
+
+    // One row for every day on which the shift overlaps the window,
+    // blank where the guard was not on duty.
+    fn print(&self, window: &Window) {
+        for start in window.occurrences(self.start, self.end) {
+            let day = DateTime::from_stamp(start).date();
+            print!("{:02}-{:02} #{:5} ", day.month(), day.day(), self.guard);
+            for minute in self.minutes(window, start) {
+                print!("{}", mark(minute));
+            }
+            println!();
+        }
+    }
+}
+
+fn mark(minute: Option<bool>) -> char {
+    match minute {
+        None => ' ',
+        Some(true) => '#',
+        Some(false) => '.',
     }
 }
 
 struct Summary {
     guard: i32,
+    shifts: i32,
     minutes: i32,
-    aslept: [i32; 60],
+    aslept: Vec<i32>,
+    observed: Vec<i32>,
     max_pos: i32,
     max_aslept: i32,
 }
 impl Summary {
-    fn new(guard: i32) -> Summary {
-        Summary{ guard, minutes: 0, aslept: [0; 60], max_pos: -1, max_aslept: -1 }
+    fn new(guard: i32, len: usize) -> Summary {
+        Summary{ guard, shifts: 0, minutes: 0, aslept: vec![0; len], observed: vec![0; len], max_pos: -1, max_aslept: -1 }
     }
     fn consider(&mut self, pos: usize) {
         if self.max_aslept >= self.aslept[pos] {
@@ -178,93 +437,68 @@ impl Shifts {
         Shifts { list: Vec::new() }
     }
 
-    fn print(&self) {
+    fn print(&self, window: &Window) {
         for shift in &self.list {
-            shift.print();
+            shift.print(window);
         }
     }
 
-    fn analize(&self) {
-        self.print();
-
+    // One summary per guard, ordered by guard; only minutes inside the
+    // window count, and observed tells on how many days the guard was on
+    // duty at each of them.
+    fn summarize(&self, window: &Window) -> Vec<Summary> {
+        let len = window.len as usize;
         let mut per_guard = HashMap::new();
         for shift in &self.list {
             let key = shift.guard;
-            if !per_guard.contains_key(&key) {
-                per_guard.insert(key, Summary::new(key));
+            let summary = per_guard.entry(key).or_insert_with(|| Summary::new(key, len));
+            summary.shifts += 1;
+            for start in window.occurrences(shift.start, shift.end) {
+                for (pos, minute) in shift.minutes(window, start).iter().enumerate() {
+                    if minute.is_some() {
+                        summary.observed[pos] += 1;
+                    }
+                }
             }
-            let mut summary = per_guard.get_mut(&key).unwrap();
-            for s in 0..60 {
-                if !shift.asleep[s] {
-                    continue;
+            for &(from, to) in &shift.naps {
+                for stamp in from..to {
+                    if let Some(pos) = window.offset(stamp) {
+                        summary.minutes += 1;
+                        summary.aslept[pos] += 1;
+                    }
                 }
-                summary.minutes += 1;
-                summary.aslept[s] += 1;
             }
         }
 
-        let mut minutes = Winner::new("minutes".to_string());
-        let mut aslept = Winner::new("aslept".to_string());
-        for (guard, mut summary) in per_guard {
-            for s in 0..60 {
+        let mut summaries: Vec<Summary> = per_guard.into_values().collect();
+        summaries.sort_by_key(|summary| summary.guard);
+        for summary in &mut summaries {
+            for s in 0..len {
                 summary.consider(s);
             }
-            summary.print();
-
-            minutes.consider(guard, summary.minutes, summary.max_pos);
-            aslept.consider(guard, summary.max_aslept, summary.max_pos);
         }
-        minutes.print();
-        aslept.print();
+        summaries
     }
-}
-
-struct Date {
-    y: i32,
-    m: i32,
-    d: i32,
-    j: i32,
-}
-impl Date {
-    const JULIAN_OFFSET: i32 = 2200000;
 
-    fn new_from_ymd(y: i32, m: i32, d: i32) -> Date {
-        Date { y, m, d, j: -1 }
-    }
-    fn new_from_julian(j: i32) -> Date {
-        Date { j, y: -1, m: -1, d: -1 }
+    fn winners(summaries: &[Summary]) -> Vec<Winner> {
+        let mut minutes = Winner::new("minutes".to_string());
+        let mut aslept = Winner::new("aslept".to_string());
+        for summary in summaries {
+            minutes.consider(summary.guard, summary.minutes, summary.max_pos);
+            aslept.consider(summary.guard, summary.max_aslept, summary.max_pos);
+        }
+        vec![minutes, aslept]
     }
 
-    fn get_julian(&mut self) -> i32 {
-        if self.j < 0 {
-            let p: i32 = (self.m - 14) / 12;
-            self.j = (1461 * (self.y + 4800 + p)) / 4 +
-                          (367 * (self.m - 2 - 12 * p)) / 12 -
-                          (3 * ((self.y + 4900 + p) / 100)) / 4 +
-                          (self.d - 32075);
-            self.j -= Date::JULIAN_OFFSET;
+    fn analize(&self, window: &Window) {
+        self.print(window);
+
+        let summaries = self.summarize(window);
+        for summary in &summaries {
+            summary.print();
         }
-        self.j
-    }
-
-    fn get_ymd(&mut self) -> (i32, i32, i32) {
-        if self.y < 0 || self.m < 0 || self.d < 0 {
-            let mut l = self.j + Date::JULIAN_OFFSET;
-            l += 68569;
-            let n = (4 * l) / 146097;
-            l -= (146097 * n + 3) / 4;
-            let i = (4000 * (l + 1)) / 1461001;
-            l -= (1461 * i) / 4 - 31;
-            let h = (80 * l) / 2447;
-            let k = h / 11;
-            self.d = l - (2447 * h) / 80;
-            self.m = h + 2 - (12 * k);
-            self.y = 100 * (n - 49) + i + k;
+        for winner in Shifts::winners(&summaries) {
+            winner.print();
         }
-        (self.y, self.m, self.d)
-    }
-
-    fn get_stamp(&mut self, h: i32, m: i32) -> i32 {
-        (self.get_julian() * 24 + h) * 60 + m
     }
 }
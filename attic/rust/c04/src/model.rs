@@ -0,0 +1,90 @@
+use super::{Shifts, Summary, Window};
+
+// Normal quantile for 95% confidence intervals.
+const Z: f64 = 1.96;
+
+// How often a guard was seen asleep at one minute: on `asleep` of the
+// `observed` days they were on duty then.
+#[derive(Copy, Clone, Debug)]
+pub struct Estimate {
+    pub asleep: i32,
+    pub observed: i32,
+}
+impl Estimate {
+    pub fn p(&self) -> f64 {
+        self.asleep as f64 / self.observed as f64
+    }
+
+    // Wilson score interval; unlike the textbook p ± z·se it stays inside
+    // [0, 1] and does not collapse to a point for guards seen only a few times.
+    pub fn interval(&self) -> (f64, f64) {
+        let n = self.observed as f64;
+        let p = self.p();
+        let z2 = Z * Z;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let half = Z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+        ((center - half).max(0.0), (center + half).min(1.0))
+    }
+
+    fn describe(&self) -> String {
+        if self.observed == 0 {
+            return "never on duty then".to_string();
+        }
+        let (low, high) = self.interval();
+        format!("asleep on {} of {} days, p = {:.3}, 95% CI [{:.3}, {:.3}]",
+                self.asleep, self.observed, self.p(), low, high)
+    }
+}
+
+// Chance of each guard being asleep at each minute of the window on their
+// next shift, taken from how often they were asleep then before.
+pub struct Model<'a> {
+    window: &'a Window,
+    summaries: Vec<Summary>,
+}
+impl<'a> Model<'a> {
+    pub fn new(shifts: &Shifts, window: &'a Window) -> Model<'a> {
+        Model { window, summaries: shifts.summarize(window) }
+    }
+
+    fn estimate(summary: &Summary, pos: usize) -> Estimate {
+        Estimate { asleep: summary.aslept[pos], observed: summary.observed[pos] }
+    }
+
+    // Answer a question given as N@HH:MM.
+    pub fn print_chance(&self, text: &str) -> Result<(), String> {
+        let bad = || format!("{}: bad question, expected GUARD@HH:MM", text);
+        let (guard, time) = text.split_once('@').ok_or_else(bad)?;
+        let guard = guard.trim_start_matches('#').parse::<i32>().map_err(|_| bad())?;
+        let (h, m) = time.split_once(':').ok_or_else(bad)?;
+        let (h, m) = (h.parse::<i64>().map_err(|_| bad())?, m.parse::<i64>().map_err(|_| bad())?);
+        if !(0..24).contains(&h) || !(0..60).contains(&m) {
+            return Err(bad());
+        }
+        let pos = self.window.offset(h * 60 + m)
+            .ok_or_else(|| format!("{}: {:02}:{:02} is outside the window", text, h, m))?;
+        let summary = self.summaries.iter().find(|summary| summary.guard == guard)
+            .ok_or_else(|| format!("{}: no shifts for guard #{}", text, guard))?;
+        println!("guard #{} at {}: {}", guard, self.window.label(pos as i32), Model::estimate(summary, pos).describe());
+        Ok(())
+    }
+
+    // The guard and minute pairs where sneaking in looks safest, ranked by
+    // the lower end of the interval so that a guard seen asleep once on the
+    // only day they were there does not come first.
+    pub fn print_safest(&self, count: usize) {
+        let mut ranked = Vec::new();
+        for summary in &self.summaries {
+            for pos in 0..summary.aslept.len() {
+                let estimate = Model::estimate(summary, pos);
+                if estimate.asleep > 0 {
+                    ranked.push((estimate.interval().0, summary.guard, pos, estimate));
+                }
+            }
+        }
+        ranked.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        for (rank, &(_, guard, pos, estimate)) in ranked.iter().take(count).enumerate() {
+            println!("{:3}. {} guard #{}: {}", rank + 1, self.window.label(pos as i32), guard, estimate.describe());
+        }
+    }
+}
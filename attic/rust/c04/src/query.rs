@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use regex::{self, Regex};
+use calendar::{Date, DateTime};
+use super::{Shifts, Window};
+
+// Questions about the shifts, one per line:
+//   at YYYY-MM-DD HH:MM                       who was on duty, and asleep?
+//   during HH:MM-HH:MM [between D1 and D2]    who slept in that part of the day?
+//   guard #N [between D1 and D2]              how long did a guard sleep?
+// Dates are inclusive; without them the whole log is considered.
+pub fn run<R: BufRead>(shifts: &Shifts, reader: R) {
+    let re = Regex::new(
+        r#"(?x)
+          ^
+          \s*
+          (?:
+            at \s+ (?P<at>\d{4}-\d{2}-\d{2} \s+ \d{2}:\d{2})
+          |
+            during \s+ (?P<during>\d{2}:\d{2}-\d{2}:\d{2})
+          |
+            guard \s+ \#(?P<guard>[0-9]+)
+          )
+          (?:
+            \s+ between \s+ (?P<from>\d{4}-\d{2}-\d{2}) \s+ and \s+ (?P<to>\d{4}-\d{2}-\d{2})
+          )?
+          \s*
+          $
+          "#).unwrap();
+
+    for line in reader.lines() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answer = match re.captures(&line) {
+            Some(caps) => answer(shifts, &caps),
+            None => Err("bad query, expected 'at DATE HH:MM', 'during HH:MM-HH:MM' or 'guard #N', \
+                         optionally followed by 'between DATE and DATE'".to_string()),
+        };
+        match answer {
+            Ok(answer) => println!("{}: {}", line.trim(), answer),
+            Err(e) => println!("{}: {}", line.trim(), e),
+        }
+    }
+}
+
+fn answer(shifts: &Shifts, caps: &regex::Captures) -> Result<String, String> {
+    let (from, to) = match (caps.name("from"), caps.name("to")) {
+        (Some(from), Some(to)) => {
+            let from = from.as_str().parse::<Date>().map_err(|e| e.to_string())?;
+            let to = to.as_str().parse::<Date>().map_err(|e| e.to_string())?;
+            if to < from {
+                return Err(format!("{} is before {}", to, from));
+            }
+            (from.at(0, 0).unwrap().stamp(), to.add_days(1).at(0, 0).unwrap().stamp())
+        }
+        _ => (i64::MIN, i64::MAX),
+    };
+
+    if let Some(at) = caps.name("at") {
+        if from != i64::MIN {
+            return Err("'at' takes no dates".to_string());
+        }
+        let time = at.as_str().parse::<DateTime>().map_err(|e| e.to_string())?;
+        return Ok(asleep_at(shifts, time.stamp()));
+    }
+    if let Some(during) = caps.name("during") {
+        let window = Window::parse(during.as_str()).ok_or_else(|| format!("{}: bad window", during.as_str()))?;
+        return Ok(asleep_during(shifts, &window, from, to));
+    }
+    let guard = caps.name("guard").unwrap().as_str().parse::<i32>().map_err(|e| e.to_string())?;
+    Ok(guard_minutes(shifts, guard, from, to))
+}
+
+fn asleep_at(shifts: &Shifts, stamp: i64) -> String {
+    let on_duty: Vec<String> = shifts.list.iter()
+        .filter(|shift| shift.start <= stamp && stamp < shift.end)
+        .map(|shift| format!("#{} {}", shift.guard, if shift.is_asleep(stamp) { "asleep" } else { "awake" }))
+        .collect();
+    if on_duty.is_empty() { "nobody on duty".to_string() } else { on_duty.join(", ") }
+}
+
+// Minutes every guard slept inside the window, on days between from and to.
+fn asleep_during(shifts: &Shifts, window: &Window, from: i64, to: i64) -> String {
+    let mut per_guard = BTreeMap::new();
+    for shift in &shifts.list {
+        let minutes = nap_minutes(shift.naps.iter(), from, to, |stamp| window.offset(stamp).is_some());
+        if minutes > 0 {
+            *per_guard.entry(shift.guard).or_insert(0) += minutes;
+        }
+    }
+    let guards: Vec<String> = per_guard.iter()
+        .map(|(guard, minutes)| format!("#{} ({} minutes)", guard, minutes))
+        .collect();
+    if guards.is_empty() { "none".to_string() } else { guards.join(", ") }
+}
+
+fn guard_minutes(shifts: &Shifts, guard: i32, from: i64, to: i64) -> String {
+    let mut found = false;
+    let mut minutes = 0;
+    for shift in shifts.list.iter().filter(|shift| shift.guard == guard) {
+        found = true;
+        minutes += nap_minutes(shift.naps.iter(), from, to, |_| true);
+    }
+    if found { format!("{} minutes", minutes) } else { format!("no shifts for guard #{}", guard) }
+}
+
+// Minutes asleep within [from, to) that also pass the filter.
+fn nap_minutes<'a, I, F>(naps: I, from: i64, to: i64, filter: F) -> i64
+    where I: Iterator<Item = &'a (i64, i64)>, F: Fn(i64) -> bool {
+    naps.map(|&(start, end)| (start.max(from)..end.min(to)).filter(|&stamp| filter(stamp)).count() as i64)
+        .sum()
+}
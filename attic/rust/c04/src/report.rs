@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use calendar::{Date, DateTime};
+use super::{mark, Shifts, Summary, Window, Winner};
+
+// Shades for the terminal heatmap, from never asleep to always asleep.
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+// Widest terminal heatmap; longer windows are folded into buckets.
+const MAX_COLUMNS: usize = 60;
+
+// What one guard did on each day, minute by minute within the window.
+pub struct Heatmap {
+    pub guard: i32,
+    pub days: BTreeMap<Date, Vec<Option<bool>>>,
+}
+
+// Everything the report needs, computed once.
+pub struct Report<'a> {
+    window: &'a Window,
+    summaries: Vec<Summary>,
+    winners: Vec<Winner>,
+    heatmaps: Vec<Heatmap>,
+}
+impl<'a> Report<'a> {
+    pub fn new(shifts: &Shifts, window: &'a Window) -> Report<'a> {
+        let summaries = shifts.summarize(window);
+        let winners = Shifts::winners(&summaries);
+
+        // a guard may be on duty twice on the same day; merge both shifts
+        let mut per_guard: BTreeMap<i32, BTreeMap<Date, Vec<Option<bool>>>> = BTreeMap::new();
+        for shift in &shifts.list {
+            let days = per_guard.entry(shift.guard).or_default();
+            for start in window.occurrences(shift.start, shift.end) {
+                let day = DateTime::from_stamp(start).date();
+                let row = days.entry(day).or_insert_with(|| vec![None; window.len as usize]);
+                for (cell, minute) in row.iter_mut().zip(shift.minutes(window, start)) {
+                    *cell = match (*cell, minute) {
+                        (Some(a), Some(b)) => Some(a || b),
+                        (a, b) => a.or(b),
+                    };
+                }
+            }
+        }
+        let heatmaps = per_guard.into_iter().map(|(guard, days)| Heatmap { guard, days }).collect();
+
+        Report { window, summaries, winners, heatmaps }
+    }
+
+    // One row per guard and day, one column per minute: 1 asleep, 0 awake,
+    // empty while not on duty.
+    pub fn write_heatmap_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "guard,day")?;
+        for pos in 0..self.window.len {
+            write!(out, ",{}", self.window.label(pos as i32))?;
+        }
+        writeln!(out)?;
+        for heatmap in &self.heatmaps {
+            for (day, row) in &heatmap.days {
+                write!(out, "{},{}", heatmap.guard, day)?;
+                for minute in row {
+                    match *minute {
+                        None => write!(out, ",")?,
+                        Some(asleep) => write!(out, ",{}", asleep as i32)?,
+                    }
+                }
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_guards_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "guard,shifts,days,minutes,top_minute,top_count")?;
+        for (summary, heatmap) in self.summaries.iter().zip(&self.heatmaps) {
+            writeln!(out, "{},{},{},{},{},{}", summary.guard, summary.shifts, heatmap.days.len(),
+                     summary.minutes, self.window.label(summary.max_pos), summary.max_aslept)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_winners_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "strategy,guard,count,minute,pos,answer")?;
+        for winner in &self.winners {
+            writeln!(out, "{},{},{},{},{},{}", winner.name, winner.guard, winner.top,
+                     self.window.label(winner.pos), winner.pos, winner.guard * winner.pos)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"window\": {{ \"start\": \"{}\", \"minutes\": {} }},", self.window.label(0), self.window.len)?;
+        writeln!(out, "  \"winners\": [")?;
+        for (index, winner) in self.winners.iter().enumerate() {
+            let sep = if index + 1 < self.winners.len() { "," } else { "" };
+            writeln!(out, "    {{ \"strategy\": \"{}\", \"guard\": {}, \"count\": {}, \"minute\": \"{}\", \"pos\": {}, \"answer\": {} }}{}",
+                     winner.name, winner.guard, winner.top, self.window.label(winner.pos), winner.pos,
+                     winner.guard * winner.pos, sep)?;
+        }
+        writeln!(out, "  ],")?;
+        writeln!(out, "  \"guards\": [")?;
+        for (index, (summary, heatmap)) in self.summaries.iter().zip(&self.heatmaps).enumerate() {
+            writeln!(out, "    {{")?;
+            writeln!(out, "      \"guard\": {},", summary.guard)?;
+            writeln!(out, "      \"shifts\": {},", summary.shifts)?;
+            writeln!(out, "      \"minutes\": {},", summary.minutes)?;
+            writeln!(out, "      \"top_minute\": \"{}\",", self.window.label(summary.max_pos))?;
+            writeln!(out, "      \"top_count\": {},", summary.max_aslept)?;
+            let aslept: Vec<String> = summary.aslept.iter().map(|count| count.to_string()).collect();
+            writeln!(out, "      \"aslept\": [{}],", aslept.join(", "))?;
+            writeln!(out, "      \"days\": [")?;
+            for (pos, (day, row)) in heatmap.days.iter().enumerate() {
+                let sep = if pos + 1 < heatmap.days.len() { "," } else { "" };
+                let marks: String = row.iter().map(|&minute| mark(minute)).collect();
+                writeln!(out, "        {{ \"day\": \"{}\", \"minutes\": \"{}\" }}{}", day, marks, sep)?;
+            }
+            writeln!(out, "      ]")?;
+            let sep = if index + 1 < self.summaries.len() { "," } else { "" };
+            writeln!(out, "    }}{}", sep)?;
+        }
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+
+    // One row per guard, shaded by how often they slept on each minute,
+    // relative to the sleepiest guard and minute overall.
+    pub fn print_heatmap(&self) {
+        let len = self.window.len as usize;
+        let width = len.div_ceil(MAX_COLUMNS);
+        let columns = len.div_ceil(width);
+        let buckets: Vec<Vec<i32>> = self.summaries.iter()
+            .map(|summary| summary.aslept.chunks(width).map(|chunk| chunk.iter().sum()).collect())
+            .collect();
+        let top = buckets.iter().flat_map(|row| row.iter()).cloned().max().unwrap_or(0).max(1);
+
+        print!("{:7} ", "");
+        for column in 0..columns {
+            print!("{}", if column % 10 == 0 { '|' } else { ' ' });
+        }
+        println!();
+        for (summary, row) in self.summaries.iter().zip(&buckets) {
+            let shades: String = row.iter()
+                .map(|&count| SHADES[((count * 4 + top - 1) / top) as usize])
+                .collect();
+            println!("#{:5}  {} {:4} minutes", summary.guard, shades, summary.minutes);
+        }
+        println!("from {}, {} minutes per column, darkest = {} times asleep",
+                 self.window.label(0), width, top);
+    }
+}
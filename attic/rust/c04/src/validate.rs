@@ -0,0 +1,86 @@
+use std::fmt;
+use log::{Event, Kind, Place};
+
+// Something in the log that does not make sense.
+#[derive(Debug)]
+pub enum Problem {
+    // a guard falls asleep or wakes up before any shift began
+    Orphan(Place),
+    // a guard falls asleep while asleep: first line, second line
+    DoubleSleep(Place, Place),
+    // a guard wakes up while awake: line of the wake, line of the last wake if any
+    DoubleWake(Place, Option<Place>),
+    // a guard is still asleep when the shift ends: line where they fell
+    // asleep, line of the next shift (none at the end of the log)
+    LeftAsleep(i32, Place, Option<Place>),
+    // two events with the same time
+    Duplicate(Place, Place),
+}
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::Orphan(ref line) =>
+                write!(f, "{}: event before any shift began", line),
+            Problem::DoubleSleep(ref first, ref line) =>
+                write!(f, "{}: falls asleep again, already asleep since {}", line, first),
+            Problem::DoubleWake(ref line, Some(ref last)) =>
+                write!(f, "{}: wakes up again, already awake since {}", line, last),
+            Problem::DoubleWake(ref line, None) =>
+                write!(f, "{}: wakes up without having fallen asleep", line),
+            Problem::LeftAsleep(guard, ref line, Some(ref next)) =>
+                write!(f, "{}: guard #{} still asleep when the shift at {} begins", line, guard, next),
+            Problem::LeftAsleep(guard, ref line, None) =>
+                write!(f, "{}: guard #{} still asleep at the end of the log", line, guard),
+            Problem::Duplicate(ref first, ref line) =>
+                write!(f, "{}: same time as {}", line, first),
+        }
+    }
+}
+
+// Walk the events in order, tracking who is on duty and whether they are
+// asleep, and collect every inconsistency found.
+pub fn check(events: &[Event]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut guard: Option<i32> = None;
+    let mut asleep: Option<&Place> = None;
+    let mut awake: Option<&Place> = None;
+    let mut prev: Option<&Event> = None;
+    for event in events {
+        if let Some(prev) = prev {
+            if prev.time == event.time {
+                problems.push(Problem::Duplicate(prev.place.clone(), event.place.clone()));
+            }
+        }
+        prev = Some(event);
+
+        match event.kind {
+            Kind::Begins(next) => {
+                if let (Some(guard), Some(line)) = (guard, asleep) {
+                    problems.push(Problem::LeftAsleep(guard, line.clone(), Some(event.place.clone())));
+                }
+                guard = Some(next);
+                asleep = None;
+                awake = None;
+            }
+            _ if guard.is_none() => problems.push(Problem::Orphan(event.place.clone())),
+            Kind::Sleeps => match asleep {
+                Some(first) => problems.push(Problem::DoubleSleep(first.clone(), event.place.clone())),
+                None => {
+                    asleep = Some(&event.place);
+                    awake = None;
+                }
+            },
+            Kind::Wakes => match asleep {
+                Some(_) => {
+                    asleep = None;
+                    awake = Some(&event.place);
+                }
+                None => problems.push(Problem::DoubleWake(event.place.clone(), awake.cloned())),
+            },
+        }
+    }
+    if let (Some(guard), Some(line)) = (guard, asleep) {
+        problems.push(Problem::LeftAsleep(guard, line.clone(), None));
+    }
+    problems
+}
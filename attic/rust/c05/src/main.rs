@@ -1,8 +1,83 @@
-use std::io::{self, BufRead};
+mod rules;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use rules::{Reaction, Rules};
 
 fn main() {
+    let mut bench = false;
+    let mut stream = false;
+    let mut print_polymer = false;
+    let mut threads = None;
+    let mut rules = None;
+    for arg in env::args().skip(1) {
+        if arg == "--bench" {
+            bench = true;
+        // react stdin as it comes, optionally writing out what is left
+        } else if arg == "--stream" {
+            stream = true;
+        } else if arg == "--print-polymer" {
+            stream = true;
+            print_polymer = true;
+        } else if let Some(name) = arg.strip_prefix("--rules=") {
+            rules = match File::open(name).map_err(|e| format!("{}: {}", name, e))
+                .and_then(|file| Rules::read(name, BufReader::new(file))) {
+                Ok(rules) => Some(rules),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+        } else if let Some(text) = arg.strip_prefix("--threads=") {
+            threads = match text.parse::<usize>() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    eprintln!("{}: bad thread count", text);
+                    std::process::exit(1);
+                }
+            };
+        } else {
+            eprintln!("{}: unknown argument", arg);
+            std::process::exit(1);
+        }
+    }
+
+    // the benchmarks always use the puzzle rules, and only part 2 uses threads
+    let conflict = if bench && stream {
+        Some(("--bench", "--stream"))
+    } else if bench && rules.is_some() {
+        Some(("--rules", "--bench"))
+    } else if threads.is_some() && (bench || stream) {
+        Some(("--threads", if bench { "--bench" } else { "--stream" }))
+    } else {
+        None
+    };
+    if let Some((a, b)) = conflict {
+        eprintln!("{} cannot be combined with {}", a, b);
+        std::process::exit(1);
+    }
+
+    let threads = threads.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let rules = rules.unwrap_or_else(Rules::case);
+    if stream {
+        if let Err(e) = react_stream(&rules, print_polymer) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let lines = read_lines();
-    process_lines(&lines);
+    if bench {
+        run_benchmarks(&lines);
+        return;
+    }
+    process_lines(&lines, &rules, threads);
 }
 
 fn read_lines() -> Vec<String> {
@@ -14,32 +89,50 @@ fn read_lines() -> Vec<String> {
     lines
 }
 
-fn process_lines(lines: &Vec<String>) {
-    part1(lines);
-    part2(lines);
+fn process_lines(lines: &[String], rules: &Rules, threads: usize) {
+    part1(lines, rules);
+    part2(lines, rules, threads);
 }
 
-fn part1(lines: &Vec<String>) {
+fn part1(lines: &[String], rules: &Rules) {
     for line in lines {
         let vec = line.as_bytes().to_vec();
         let len_orig = vec.len();
-        let len_reacted = react(&vec);
+        let len_reacted = react(&vec, rules).len();
         println!("{} => {}", len_orig, len_reacted);
     }
 }
 
-fn part2(lines: &Vec<String>) {
-    let mut min_unit = ' ' as u8;
-    let mut min_len = std::usize::MAX;
+// With the puzzle rules, removing a unit can only add reactions, never undo
+// one, so every trial reacts the already reacted polymer instead of the raw
+// line. That does not hold for other rules (a removed unit may have been
+// produced by a reaction, or have stopped one), so those trials start from
+// the raw line. Either way the lengths shown come from the raw line.
+fn part2(lines: &[String], rules: &Rules, threads: usize) {
+    let mut min_unit = b' ';
+    let mut min_len = usize::MAX;
     for line in lines {
-        let bytes = line.as_bytes();
-        let (beg, end) = limits(bytes);
-        println!("Scanning from {} to {}", beg as char, end as char);
-        for unit in beg..end+1 { // interval is [beg, end)
-            let mut vec = bytes.to_vec();
-            remove_unit(unit, &mut vec);
-            let len_orig = vec.len();
-            let len_reacted = react(&vec);
+        let raw = line.as_bytes();
+        let (polymer, units) = if raw.is_empty() {
+            (Vec::new(), Vec::new())
+        } else if rules.is_case() {
+            let (beg, end) = limits(raw);
+            (react(raw, rules), (beg..end+1).collect::<Vec<u8>>()) // interval is [beg, end)
+        } else {
+            // every unit found in the line, both cases counting as one
+            let mut present = [false; 256];
+            for &unit in raw {
+                present[unit.to_ascii_lowercase() as usize] = true;
+            }
+            (raw.to_vec(), (0..=255).filter(|&unit| present[unit as usize]).collect())
+        };
+        if units.is_empty() {
+            println!("Nothing to scan");
+            continue;
+        }
+        println!("Scanning from {} to {}", units[0] as char, units[units.len() - 1] as char);
+        for (unit, len_reacted) in try_units(&polymer, &units, rules, threads) {
+            let len_orig = raw.iter().filter(|&&curr| !is_unit(curr, unit)).count();
             println!("{}: {} => {}", unit as char, len_orig, len_reacted);
             if min_len > len_reacted {
                 min_len = len_reacted;
@@ -50,8 +143,120 @@ fn part2(lines: &Vec<String>) {
     }
 }
 
-fn react(polymer: &Vec<u8>) -> usize {
-    let mut collapsed = polymer.clone();
+// Remove each unit in turn and react what is left, spreading the units over
+// a few threads. Each thread reuses its own buffers across trials. Returns
+// (unit, length after reacting), sorted by unit, so the result does not
+// depend on the number of threads.
+fn try_units(polymer: &[u8], units: &[u8], rules: &Rules, threads: usize) -> Vec<(u8, usize)> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(units.len()));
+    thread::scope(|scope| {
+        for _ in 0..threads.min(units.len()) {
+            scope.spawn(|| {
+                let mut removed = Vec::with_capacity(polymer.len());
+                let mut stack = Vec::with_capacity(polymer.len());
+                loop {
+                    let pos = next.fetch_add(1, Ordering::Relaxed);
+                    if pos >= units.len() {
+                        break;
+                    }
+                    removed.clear();
+                    removed.extend_from_slice(polymer);
+                    remove_unit(units[pos], &mut removed);
+                    react_into(&removed, rules, &mut stack);
+                    results.lock().unwrap().push((units[pos], stack.len()));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort();
+    results
+}
+
+// React the polymer in a single pass: every unit either reacts with the one
+// on top of the stack, which is what is left of everything before it, or is
+// pushed on it. A unit produced by a reaction goes on reacting with the new
+// top, so with replacement rules the leftmost reactions always win.
+fn react(polymer: &[u8], rules: &Rules) -> Vec<u8> {
+    let mut stack: Vec<u8> = Vec::with_capacity(polymer.len());
+    react_into(polymer, rules, &mut stack);
+    stack
+}
+
+// Same as react, leaving the result in a caller's buffer.
+fn react_into(polymer: &[u8], rules: &Rules, stack: &mut Vec<u8>) {
+    stack.clear();
+    for &unit in polymer {
+        add_unit(unit, rules, stack);
+    }
+}
+
+fn add_unit(unit: u8, rules: &Rules, stack: &mut Vec<u8>) {
+    let mut unit = unit;
+    loop {
+        let reaction = match stack.last() {
+            Some(&top) => rules.reaction(top, unit),
+            None => Reaction::Inert,
+        };
+        match reaction {
+            Reaction::Inert => stack.push(unit),
+            Reaction::Annihilate => {
+                stack.pop();
+            }
+            Reaction::Replace(product) => {
+                stack.pop();
+                unit = product;
+                continue;
+            }
+        }
+        break;
+    }
+}
+
+// React all of stdin as a single polymer, a chunk at a time, so that only
+// the units that have not reacted yet are ever kept in memory. Whitespace,
+// such as line breaks from the tool producing the polymer, is skipped.
+// The lengths go to stderr when the polymer itself is written to stdout.
+fn react_stream(rules: &Rules, print_polymer: bool) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut chunk = vec![0; 1 << 16];
+    let mut stack = Vec::new();
+    let mut units: u64 = 0;
+    loop {
+        let count = match input.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for &unit in &chunk[..count] {
+            if unit.is_ascii_whitespace() {
+                continue;
+            }
+            units += 1;
+            add_unit(unit, rules, &mut stack);
+        }
+    }
+
+    if print_polymer {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(&stack)?;
+        writeln!(out)?;
+        eprintln!("{} => {}", units, stack.len());
+    } else {
+        println!("{} => {}", units, stack.len());
+    }
+    Ok(())
+}
+
+// The original engine: rescan the whole polymer until nothing changes.
+// Quadratic when reactions nest, as in "abcCBA"; it only knows the puzzle
+// rule and is kept for --bench.
+fn react_rescan(polymer: &[u8]) -> usize {
+    let mut collapsed = polymer.to_vec();
     let mut pos;
     loop {
         pos = 0;
@@ -83,13 +288,16 @@ fn react(polymer: &Vec<u8>) -> usize {
     pos
 }
 
+// Whether curr is the given unit, in either case.
+fn is_unit(curr: u8, unit: u8) -> bool {
+    curr == unit.to_ascii_uppercase() || curr == unit.to_ascii_lowercase()
+}
+
 fn remove_unit(unit: u8, polymer: &mut Vec<u8>) {
     let mut pos = 0;
-    let upp = unit.to_ascii_uppercase();
-    let low = unit.to_ascii_lowercase();
     for j in 0..polymer.len() {
         let curr = polymer[j];
-        if curr == upp || curr == low {
+        if is_unit(curr, unit) {
             continue;
         }
         polymer[pos] = curr;
@@ -112,3 +320,58 @@ fn limits(bytes: &[u8]) -> (u8, u8) {
     }
     (beg, end)
 }
+
+// Compare both engines on the input polymers and on synthetic worst cases,
+// checking they agree.
+fn run_benchmarks(lines: &[String]) {
+    let rules = Rules::case();
+    let mut cases: Vec<(String, Vec<u8>)> = lines.iter().enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(pos, line)| (format!("input line {}", pos + 1), line.as_bytes().to_vec()))
+        .collect();
+    let n = 5000;
+    cases.push((format!("nested, {} pairs", n), nested(n)));
+    cases.push((format!("alternating, {} pairs", n), b"aA".repeat(n)));
+    cases.push((format!("inert, {} units", 2 * n), b"abcd".iter().cycle().take(2 * n).cloned().collect()));
+    cases.push((format!("random, {} units", 2 * n), random_polymer(2 * n, 5)));
+
+    println!("{:24} {:>8} {:>8} {:>12} {:>12} {:>8}", "case", "units", "reacted", "rescan", "stack", "speedup");
+    for (name, polymer) in &cases {
+        let (rescan, rescan_time) = time(|| react_rescan(polymer));
+        let (stack, stack_time) = time(|| react(polymer, &rules).len());
+        if rescan != stack {
+            println!("{}: engines disagree, rescan {} stack {}", name, rescan, stack);
+            std::process::exit(1);
+        }
+        println!("{:24} {:>8} {:>8} {:>10}us {:>10}us {:>7.1}x", name, polymer.len(), stack,
+                 rescan_time.as_micros(), stack_time.as_micros(),
+                 rescan_time.as_secs_f64() / stack_time.as_secs_f64().max(1e-9));
+    }
+}
+
+fn time<T, F: FnMut() -> T>(mut f: F) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+// "abc...CBA": each rescan only removes the innermost pair.
+fn nested(pairs: usize) -> Vec<u8> {
+    let mut polymer: Vec<u8> = (0..pairs).map(|j| b'a' + (j % 26) as u8).collect();
+    let upper: Vec<u8> = polymer.iter().rev().map(|unit| unit.to_ascii_uppercase()).collect();
+    polymer.extend(upper);
+    polymer
+}
+
+// A few letters in random case, so that reactions are frequent; a simple
+// xorshift keeps runs reproducible.
+fn random_polymer(len: usize, letters: u8) -> Vec<u8> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    (0..len).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let unit = b'a' + (state % letters as u64) as u8;
+        if (state >> 32) & 1 == 1 { unit.to_ascii_uppercase() } else { unit }
+    }).collect()
+}
@@ -0,0 +1,99 @@
+use std::io::BufRead;
+
+// What happens when two units meet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Reaction {
+    Inert,
+    Annihilate,
+    Replace(u8),
+}
+
+// Which pairs of units react, and how; a pair reacts the same way whichever
+// unit comes first.
+pub struct Rules {
+    table: Vec<Reaction>,
+}
+impl Rules {
+    fn empty() -> Rules {
+        Rules { table: vec![Reaction::Inert; 256 * 256] }
+    }
+
+    // The puzzle rule: the same letter with opposite case annihilates.
+    pub fn case() -> Rules {
+        let mut rules = Rules::empty();
+        rules.add_case();
+        rules
+    }
+
+    fn add_case(&mut self) {
+        for low in b'a'..=b'z' {
+            let upp = low.to_ascii_uppercase();
+            self.table[Rules::slot(low, upp)] = Reaction::Annihilate;
+            self.table[Rules::slot(upp, low)] = Reaction::Annihilate;
+        }
+    }
+
+    // Read rules, one per line:
+    //   a B        a and B annihilate
+    //   a B -> c   a and B turn into c
+    //   case       every letter annihilates with itself in the other case
+    // Units are single characters other than '#', so that lines starting
+    // with '#' are always comments.
+    // A later rule for the same pair replaces an earlier one, so "case" can
+    // be followed by exceptions.
+    pub fn read<R: BufRead>(name: &str, reader: R) -> Result<Rules, String> {
+        let mut rules = Rules::empty();
+        for (pos, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| format!("{}: {}", name, e))?;
+            let text = line.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            let bad = |why: &str| format!("{}:{}: {}: '{}'", name, pos + 1, why, text);
+            if text == "case" {
+                rules.add_case();
+                continue;
+            }
+
+            let (pair, product) = match text.split_once("->") {
+                Some((pair, product)) => (pair, Some(product)),
+                None => (text, None),
+            };
+            let units: Vec<&str> = pair.split_whitespace().collect();
+            if units.len() != 2 {
+                return Err(bad("expected two units"));
+            }
+            let a = Rules::unit(units[0]).ok_or_else(|| bad("units must be single characters other than '#'"))?;
+            let b = Rules::unit(units[1]).ok_or_else(|| bad("units must be single characters other than '#'"))?;
+            let reaction = match product {
+                Some(product) => Reaction::Replace(Rules::unit(product.trim())
+                    .ok_or_else(|| bad("a pair turns into a single character other than '#'"))?),
+                None => Reaction::Annihilate,
+            };
+
+            rules.table[Rules::slot(a, b)] = reaction;
+            rules.table[Rules::slot(b, a)] = reaction;
+        }
+        Ok(rules)
+    }
+
+    fn unit(text: &str) -> Option<u8> {
+        match text.as_bytes() {
+            &[unit] if unit.is_ascii_graphic() && unit != b'#' => Some(unit),
+            _ => None,
+        }
+    }
+
+    fn slot(a: u8, b: u8) -> usize {
+        (a as usize) << 8 | b as usize
+    }
+
+    // Whether these are exactly the puzzle rules.
+    pub fn is_case(&self) -> bool {
+        self.table == Rules::case().table
+    }
+
+    pub fn reaction(&self, a: u8, b: u8) -> Reaction {
+        self.table[Rules::slot(a, b)]
+    }
+}